@@ -0,0 +1,445 @@
+use anyhow::{Context, Result, bail};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+use crate::config::CachePolicy;
+use crate::download::{DownloadOutcome, DownloadRequest, Validators, download_file};
+use crate::util::{
+    Checksum, aviutl2_cache_dir, cache_dir, extract_zip_entries, remove_path, safe_join,
+    verify_checksum, zip_entries_matching,
+};
+
+const INDEX_FILE: &str = "index.json";
+const INDEX_VERSION: u32 = 1;
+const HTTP_DIR: &str = "http";
+
+/// URL とキャッシュしたファイルの対応表。`.aviutl2-cli/cache/index.json` に保存されます。
+#[derive(Default, Serialize, Deserialize)]
+pub struct CacheIndex {
+    pub version: u32,
+    pub entries: BTreeMap<String, CacheEntry>,
+    /// GitHub のリリースの添付ファイルを解決した結果（`owner/repo@tag#asset` → URL）
+    #[serde(default)]
+    pub releases: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// キャッシュディレクトリからの相対パス
+    pub path: String,
+    pub size: u64,
+    #[serde(flatten)]
+    pub validators: Validators,
+    /// 最後に取得・確認した時刻（UNIX 秒）
+    pub fetched_at: u64,
+    /// 最後に使用した時刻（UNIX 秒）
+    pub last_used: u64,
+}
+
+impl CacheIndex {
+    /// インデックスを読み込みます。
+    pub fn load(cache_dir: &Path) -> Result<Self> {
+        let index_path = cache_dir.join(INDEX_FILE);
+        let mut index = if index_path.exists() {
+            let content = fs::read_to_string(&index_path)?;
+            match serde_json::from_str::<CacheIndex>(&content) {
+                Ok(index) => index,
+                Err(err) => {
+                    log::warn!(
+                        "キャッシュのインデックスが壊れているため作り直します: {}: {}",
+                        index_path.display(),
+                        err
+                    );
+                    CacheIndex::default()
+                }
+            }
+        } else {
+            CacheIndex::default()
+        };
+        index.version = INDEX_VERSION;
+        Ok(index)
+    }
+
+    pub fn save(&self, cache_dir: &Path) -> Result<()> {
+        fs::create_dir_all(cache_dir)?;
+        let index_path = cache_dir.join(INDEX_FILE);
+        let temp_path = cache_dir.join(format!("{INDEX_FILE}.tmp"));
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, &index_path)?;
+        Ok(())
+    }
+
+    /// `<URL のハッシュ>_<ファイル名>` で直下に置かれていた以前の形式の `url` のキャッシュがあれば、
+    /// 新しい配置に移します。
+    fn adopt_legacy_file(&mut self, cache_dir: &Path, url: &str) -> Result<bool> {
+        let legacy_path = cache_dir.join(format!(
+            "{}_{}",
+            legacy_url_hash(url),
+            filename_from_url(url)
+        ));
+        if !legacy_path.is_file() {
+            return Ok(false);
+        }
+        let fetched_at = modified_secs(&legacy_path);
+        self.adopt(
+            cache_dir,
+            url,
+            &legacy_path,
+            Validators::default(),
+            fetched_at,
+        )?;
+        Ok(true)
+    }
+
+    fn adopt(
+        &mut self,
+        cache_dir: &Path,
+        url: &str,
+        file: &Path,
+        validators: Validators,
+        fetched_at: u64,
+    ) -> Result<()> {
+        let relative = entry_path(url);
+        let destination = cache_dir.join(&relative);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if file != destination {
+            remove_path(&destination)?;
+            fs::rename(file, &destination)?;
+        }
+        log::info!("既存のキャッシュをインデックスに登録しました: {}", url);
+        self.entries.insert(
+            url.to_string(),
+            CacheEntry {
+                path: relative,
+                size: fs::metadata(&destination)?.len(),
+                validators,
+                fetched_at,
+                last_used: fetched_at,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// HTTP の source を取得し、キャッシュしたファイルのパスを返します。
+/// `headers` はリクエストにだけ使い、インデックスには保存しません。
+pub fn fetch(
+    url: &str,
+    headers: &[(String, String)],
+    checksum: &Checksum,
+    policy: CachePolicy,
+    refresh: bool,
+) -> Result<PathBuf> {
+    let cache_dir = cache_dir()?;
+    let mut index = CacheIndex::load(&cache_dir)?;
+    let relative = entry_path(url);
+    let cache_path = cache_dir.join(&relative);
+    if !index.entries.contains_key(url)
+        && !index.adopt_legacy_file(&cache_dir, url)?
+        && cache_path.is_file()
+    {
+        // インデックスだけが失われた場合
+        index.adopt(
+            &cache_dir,
+            url,
+            &cache_path,
+            Validators::default(),
+            modified_secs(&cache_path),
+        )?;
+    }
+
+    let mut validators = None;
+    if let Some(entry) = index.entries.get_mut(url)
+        && cache_path.is_file()
+        && !refresh
+    {
+        match verify_checksum(&cache_path, checksum) {
+            Ok(()) => {
+                if !needs_revalidation(policy, entry) {
+                    log::info!(
+                        "source のキャッシュを使用します: {} -> {}",
+                        url,
+                        cache_path.display()
+                    );
+                    entry.last_used = unix_now();
+                    index.save(&cache_dir)?;
+                    return Ok(cache_path);
+                }
+                validators = Some(entry.validators.clone()).filter(|v| !v.is_empty());
+            }
+            Err(err) => {
+                log::warn!("キャッシュを破棄して再取得します: {:#}", err);
+            }
+        }
+    }
+
+    // 検証に失敗した場合は以前のキャッシュを残す
+    let request = DownloadRequest {
+        headers,
+        validators: validators.as_ref(),
+        checksum: *checksum,
+        ..DownloadRequest::new(url)
+    };
+    let outcome = download_file(&request, &cache_path)
+        .with_context(|| format!("source のダウンロードに失敗しました: {url}"))?;
+    let validators = match outcome {
+        DownloadOutcome::NotModified => {
+            log::info!(
+                "source は更新されていないためキャッシュを使用します: {} -> {}",
+                url,
+                cache_path.display()
+            );
+            validators.unwrap_or_default()
+        }
+        DownloadOutcome::Downloaded(validators) => {
+            log::info!(
+                "source をダウンロードしました: {} -> {}",
+                url,
+                cache_path.display()
+            );
+            validators
+        }
+    };
+    let now = unix_now();
+    index.entries.insert(
+        url.to_string(),
+        CacheEntry {
+            path: relative,
+            size: fs::metadata(&cache_path)?.len(),
+            validators,
+            fetched_at: now,
+            last_used: now,
+        },
+    );
+    index.save(&cache_dir)?;
+    Ok(cache_path)
+}
+
+/// 以前に解決した GitHub のリリースの添付ファイルの URL を返します。
+pub fn recorded_release_asset(key: &str) -> Result<Option<String>> {
+    let cache_dir = cache_dir()?;
+    Ok(CacheIndex::load(&cache_dir)?.releases.get(key).cloned())
+}
+
+pub fn record_release_asset(key: &str, url: &str) -> Result<()> {
+    let cache_dir = cache_dir()?;
+    let mut index = CacheIndex::load(&cache_dir)?;
+    if index.releases.get(key).map(String::as_str) != Some(url) {
+        index.releases.insert(key.to_string(), url.to_string());
+        index.save(&cache_dir)?;
+    }
+    Ok(())
+}
+
+/// キャッシュした zip から `pattern` に一致するファイルを展開し、展開先と一致したエントリ名を返します。
+/// zip の内容が前回から変わっていなければ展開済みのものをそのまま使います。
+pub fn extract(archive: &Path, pattern: &str) -> Result<(PathBuf, Vec<String>)> {
+    let names = zip_entries_matching(archive, pattern)?;
+    if names.is_empty() {
+        bail!(
+            "zip 内に path に一致するファイルがありません: {} ({})",
+            pattern,
+            archive.display()
+        );
+    }
+    let key = format!("{:016x}", xxhash_rust::xxh3::xxh3_64(pattern.as_bytes()));
+    let extract_dir = archive.with_file_name(format!("extracted-{key}"));
+    let stamp_path = archive.with_file_name(format!("extracted-{key}.stamp"));
+    let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+    let mut reader = BufReader::new(fs::File::open(archive)?);
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        hasher.update(buf);
+        let read = buf.len();
+        reader.consume(read);
+    }
+    let stamp = format!("{:032x}", hasher.digest128());
+    let up_to_date = fs::read_to_string(&stamp_path).is_ok_and(|current| current == stamp)
+        && names
+            .iter()
+            .all(|name| safe_join(&extract_dir, name).is_ok_and(|path| path.is_file()));
+    if !up_to_date {
+        remove_path(&extract_dir)?;
+        extract_zip_entries(archive, &extract_dir, &names)?;
+        fs::write(&stamp_path, stamp)?;
+        log::info!(
+            "zip から展開しました: {} ({} 件)",
+            archive.display(),
+            names.len()
+        );
+    }
+    Ok((extract_dir, names))
+}
+
+/// 削除したファイルの数と合計サイズ
+#[derive(Default)]
+pub struct Removed {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// `max_age` 以上使われていない source と AviUtl2 の zip のキャッシュと、インデックスにないファイルを削除します。
+/// 以前の形式のキャッシュは、`urls` のものを先に新しい配置へ移し、残りは更新日時で判断します。
+pub fn prune(max_age: Duration, urls: &[String]) -> Result<Removed> {
+    let cache_dir = cache_dir()?;
+    let mut removed = Removed::default();
+    if !cache_dir.exists() {
+        return Ok(removed);
+    }
+    let mut index = CacheIndex::load(&cache_dir)?;
+    let released = index.releases.values().cloned().collect::<Vec<_>>();
+    for url in urls.iter().chain(&released) {
+        if !index.entries.contains_key(url) {
+            index.adopt_legacy_file(&cache_dir, url)?;
+        }
+    }
+    let now = unix_now();
+    index.entries.retain(|url, entry| {
+        let path = cache_dir.join(&entry.path);
+        if !path.is_file() {
+            return false;
+        }
+        if now.saturating_sub(entry.last_used) < max_age.as_secs() {
+            return true;
+        }
+        log::info!("キャッシュを削除します: {}", url);
+        removed.files += 1;
+        removed.bytes += entry.size;
+        fs::remove_file(&path).is_err()
+    });
+
+    // 展開したファイルなども含め、エントリのディレクトリにあるものは残す
+    let referenced = index
+        .entries
+        .values()
+        .filter_map(|entry| cache_dir.join(&entry.path).parent().map(Path::to_path_buf))
+        .collect::<Vec<_>>();
+    let http_dir = cache_dir.join(HTTP_DIR);
+    if http_dir.exists() {
+        for entry in WalkDir::new(&http_dir).contents_first(true) {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type().is_dir() {
+                if path != http_dir && fs::read_dir(path)?.next().is_none() {
+                    fs::remove_dir(path)?;
+                }
+            } else if !referenced.iter().any(|dir| path.starts_with(dir)) {
+                removed.files += 1;
+                removed.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+                fs::remove_file(path)?;
+            }
+        }
+    }
+    // 取得元が分からず移行できなかった以前の形式のキャッシュ
+    for entry in fs::read_dir(&cache_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_file()
+            && entry.file_name() != INDEX_FILE
+            && now.saturating_sub(modified_secs(&path)) >= max_age.as_secs()
+        {
+            log::info!("以前の形式のキャッシュを削除します: {}", path.display());
+            removed.files += 1;
+            removed.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            fs::remove_file(&path)?;
+        }
+    }
+    // AviUtl2 の zip は使うたびに更新日時を更新している
+    let aviutl2_dir = aviutl2_cache_dir()?;
+    if aviutl2_dir.exists() {
+        for entry in fs::read_dir(&aviutl2_dir)? {
+            let path = entry?.path();
+            if !path.is_file() || now.saturating_sub(modified_secs(&path)) < max_age.as_secs() {
+                continue;
+            }
+            log::info!("キャッシュを削除します: {}", path.display());
+            removed.files += 1;
+            removed.bytes += fs::metadata(&path)?.len();
+            fs::remove_file(&path)?;
+        }
+    }
+    index.save(&cache_dir)?;
+    Ok(removed)
+}
+
+/// キャッシュディレクトリを丸ごと削除します。
+pub fn clean() -> Result<Removed> {
+    let cache_dir = cache_dir()?;
+    let mut removed = Removed::default();
+    if !cache_dir.exists() {
+        return Ok(removed);
+    }
+    for entry in WalkDir::new(&cache_dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            removed.files += 1;
+            removed.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+        }
+    }
+    remove_path(&cache_dir)?;
+    Ok(removed)
+}
+
+fn needs_revalidation(policy: CachePolicy, entry: &CacheEntry) -> bool {
+    match policy {
+        CachePolicy::Forever => false,
+        CachePolicy::Revalidate => true,
+        CachePolicy::Ttl(ttl) => unix_now().saturating_sub(entry.fetched_at) >= ttl.as_secs(),
+    }
+}
+
+/// URL のキャッシュ先（キャッシュディレクトリからの相対パス）。
+/// キーには Rust のバージョンに依存しない xxh3 を使います。
+fn entry_path(url: &str) -> String {
+    format!(
+        "{HTTP_DIR}/{:032x}/{}",
+        xxhash_rust::xxh3::xxh3_128(url.as_bytes()),
+        filename_from_url(url)
+    )
+}
+
+/// 以前のバージョンがキャッシュのファイル名に使っていたハッシュ。
+/// Rust のバージョンによって値が変わるため、移行のためだけに残しています。
+fn legacy_url_hash(url: &str) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    url.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn filename_from_url(url: &str) -> String {
+    let url = url.split('#').next().unwrap_or(url);
+    let url = url.split('?').next().unwrap_or(url);
+    let name = url.rsplit('/').next().unwrap_or("download");
+    if name.is_empty() {
+        "download".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+pub fn modified_secs(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
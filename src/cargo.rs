@@ -0,0 +1,223 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::{BUILTIN_PROFILES, CargoSource, ResolvedProfile};
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    kind: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    reason: String,
+    #[serde(default)]
+    target: Option<Target>,
+    #[serde(default)]
+    filenames: Vec<PathBuf>,
+}
+
+/// cargo でビルドする成果物
+#[derive(Clone)]
+pub struct CargoBuild {
+    /// `cargo build` に渡す引数
+    pub args: Vec<String>,
+    /// 生成される cdylib のパス
+    pub output: PathBuf,
+}
+
+/// `cargo metadata` からパッケージの cdylib が生成される場所と、ビルドに使う引数を求めます。
+/// `profile` は au2 のプロファイル（省略時は debug）です。
+pub fn locate(
+    source: &CargoSource,
+    profile: Option<&ResolvedProfile>,
+    root: &Path,
+) -> Result<CargoBuild> {
+    let cargo_profile = match &source.cargo_profile {
+        Some(cargo_profile) => cargo_profile.clone(),
+        None => default_cargo_profile(profile)?.to_string(),
+    };
+
+    let mut common_args = Vec::new();
+    if let Some(manifest_path) = &source.manifest_path {
+        common_args.push("--manifest-path".to_string());
+        common_args.push(manifest_path.clone());
+    }
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .args(&common_args)
+        .current_dir(working_dir(root))
+        .stderr(Stdio::inherit())
+        .output()
+        .context("cargo コマンドの実行に失敗しました")?;
+    if !output.status.success() {
+        bail!("cargo metadata が失敗しました");
+    }
+    let metadata: Metadata =
+        serde_json::from_slice(&output.stdout).context("cargo metadata の解析に失敗しました")?;
+    let package = metadata
+        .packages
+        .iter()
+        .find(|package| package.name == source.cargo)
+        .with_context(|| format!("cargo のパッケージが見つかりません: {}", source.cargo))?;
+    let target = package
+        .targets
+        .iter()
+        .find(|target| target.kind.iter().any(|kind| kind == "cdylib"))
+        .with_context(|| {
+            format!(
+                "パッケージ {} に cdylib がありません（[lib] crate-type = [\"cdylib\"] が必要です）",
+                source.cargo
+            )
+        })?;
+
+    let mut dir = metadata.target_directory.clone();
+    if let Some(target) = &source.target {
+        dir.push(target);
+    }
+    dir.push(match cargo_profile.as_str() {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        profile => profile,
+    });
+    let (prefix, suffix) = library_affixes(source.target.as_deref());
+    let output = dir.join(format!("{prefix}{}{suffix}", target.name.replace('-', "_")));
+
+    let mut args = vec![
+        "build".to_string(),
+        "--package".to_string(),
+        source.cargo.clone(),
+        "--lib".to_string(),
+        "--profile".to_string(),
+        cargo_profile,
+    ];
+    if let Some(target) = &source.target {
+        args.push("--target".to_string());
+        args.push(target.clone());
+    }
+    args.extend(common_args);
+    Ok(CargoBuild { args, output })
+}
+
+/// `cargo build` を実行し、JSON のメッセージから生成された cdylib が想定した場所にあることを確認します。
+pub fn build(
+    build: &CargoBuild,
+    working_dir: Option<&Path>,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    log::info!("コマンド実行: cargo {}", build.args.join(" "));
+    let mut child = Command::new("cargo")
+        .args(&build.args)
+        .envs(env)
+        .arg("--message-format=json-render-diagnostics")
+        .current_dir(working_dir.unwrap_or(Path::new(".")))
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("cargo コマンドの実行に失敗しました")?;
+    let stdout = child
+        .stdout
+        .take()
+        .context("cargo の出力を取得できません")?;
+    let mut produced = Vec::new();
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        let Ok(message) = serde_json::from_str::<Message>(&line) else {
+            continue;
+        };
+        if message.reason == "compiler-artifact"
+            && message
+                .target
+                .is_some_and(|target| target.kind.iter().any(|kind| kind == "cdylib"))
+        {
+            produced.extend(message.filenames);
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!(
+            "ビルドコマンドが失敗しました: cargo {}",
+            build.args.join(" ")
+        );
+    }
+    let expected = std::path::absolute(&build.output)?;
+    if !produced
+        .iter()
+        .any(|path| std::path::absolute(path).is_ok_and(|path| path == expected))
+    {
+        bail!(
+            "cargo が生成した cdylib が想定した場所にありません: {}\n  生成されたファイル: {}",
+            build.output.display(),
+            produced
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// 継承元をたどって最初に見つかった組み込みのプロファイルに対応する cargo のプロファイルを返します。
+/// debug は dev、release は release です。
+fn default_cargo_profile(profile: Option<&ResolvedProfile>) -> Result<&'static str> {
+    let Some(profile) = profile else {
+        return Ok("dev");
+    };
+    match profile
+        .chain
+        .iter()
+        .find(|name| BUILTIN_PROFILES.contains(&name.as_str()))
+        .map(String::as_str)
+    {
+        Some("debug") => Ok("dev"),
+        Some(_) => Ok("release"),
+        None => bail!(
+            "プロファイル {} は debug / release を継承していないため、cargo_profile の指定が必要です",
+            profile.name
+        ),
+    }
+}
+
+fn working_dir(root: &Path) -> &Path {
+    if root.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        root
+    }
+}
+
+/// ターゲットごとの動的ライブラリのファイル名の前後
+fn library_affixes(target: Option<&str>) -> (&'static str, &'static str) {
+    let windows = match target {
+        Some(target) => target.contains("windows"),
+        None => cfg!(windows),
+    };
+    let apple = match target {
+        Some(target) => target.contains("apple"),
+        None => cfg!(target_vendor = "apple"),
+    };
+    if windows {
+        ("", ".dll")
+    } else if apple {
+        ("lib", ".dylib")
+    } else {
+        ("lib", ".so")
+    }
+}
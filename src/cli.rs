@@ -1,8 +1,13 @@
 use clap::Subcommand;
+use std::path::PathBuf;
 
 #[derive(clap::Parser)]
 #[command(name = "au2", version, about = "AviUtl2 CLI")]
 pub struct Cli {
+    /// 使う設定ファイル（省略時はカレントディレクトリから親へ順に aviutl2.toml / .config/aviutl2.toml を探します）
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -12,6 +17,20 @@ pub enum Commands {
     /// aviutl2.toml を作成します
     Init,
 
+    /// aviutl2.toml を JSON Schema と設定同士の整合性で検証します
+    Check,
+
+    /// 設定を表示します
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
+    /// 古い形式の aviutl2.toml を現在の形式に書き換えます（コメントは残します）
+    Migrate {
+        /// 差分を表示するだけで書き込みません
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// AviUtl2 の開発環境をセットアップします
     /// （prepare:schema -> prepare:aviutl2 -> prepare:artifacts）
     Prepare {
@@ -19,6 +38,10 @@ pub enum Commands {
         #[arg(short, long)]
         force: bool,
 
+        /// 使うプロファイル名（デフォルトは debug）
+        #[arg(short = 'p', long = "profile")]
+        profile: Option<String>,
+
         /// HTTP の成果物キャッシュを再取得します
         #[arg(short, long)]
         refresh: bool,
@@ -30,7 +53,20 @@ pub enum Commands {
 
     /// AviUtl2 本体をダウンロードし、開発用ディレクトリに展開します
     #[command(name = "prepare:aviutl2")]
-    PrepareAviUtl2,
+    PrepareAviUtl2 {
+        /// アップグレード後に成果物を再配置するときのプロファイル名
+        /// （デフォルトは前回 prepare:artifacts で使ったもの）
+        #[arg(short = 'p', long = "profile")]
+        profile: Option<String>,
+    },
+
+    /// AviUtl2 のバージョンを確認します
+    #[command(name = "aviutl2", subcommand)]
+    Aviutl2(Aviutl2Commands),
+
+    /// ダウンロードのキャッシュを管理します
+    #[command(subcommand)]
+    Cache(CacheCommands),
 
     /// 成果物を開発用ディレクトリに配置します
     #[command(name = "prepare:artifacts")]
@@ -96,3 +132,71 @@ pub enum Commands {
         args: Vec<String>,
     },
 }
+
+#[derive(Subcommand)]
+pub enum Aviutl2Commands {
+    /// 利用できる AviUtl2 のバージョンを一覧表示します
+    List {
+        /// JSON で出力します
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// インストール済みの AviUtl2 を一覧表示します
+    Installed {
+        /// JSON で出力します
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// キャッシュの内容をサイズと最終使用日時つきで一覧表示します
+    List {
+        /// JSON で出力します
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// しばらく使われていないキャッシュと不要なファイルを削除します
+    Prune {
+        /// この期間使われていないキャッシュを削除します（例: 30d, 12h）
+        #[arg(long = "older-than", default_value = "30d")]
+        older_than: String,
+    },
+
+    /// キャッシュをすべて削除します
+    Clean,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// コマンドで実際に使われる設定を表示します。
+    /// --origin を指定すると、aviutl2.local.toml を重ねた後の設定をそのまま表示します
+    Show {
+        /// aviutl2.local.toml を重ねた後の設定を、値ごとにどのファイルから来たかを添えて表示します
+        #[arg(long, conflicts_with_all = ["profile", "command", "json"])]
+        origin: bool,
+
+        /// 使うプロファイル名（デフォルトはコマンドごとの設定に従います）
+        #[arg(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// 設定を解決するコマンド（デフォルトは develop）
+        #[arg(long, value_enum)]
+        command: Option<ConfigTarget>,
+
+        /// JSON で出力します
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// `au2 config show --command` で指定するコマンド
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ConfigTarget {
+    Develop,
+    Preview,
+    Release,
+}
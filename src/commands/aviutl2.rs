@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::config::{Config, load_config_if_exists};
+use crate::download::fetch_json;
+use crate::util::{aviutl2_cache_dir, development_dir, preview_dir};
+
+/// `{api_base}/versions` が返す配列の要素。配列は新しい順に並んでいるものとします。
+#[derive(Deserialize)]
+struct ApiRelease {
+    version: String,
+    date: Option<String>,
+    notes: Option<String>,
+}
+
+/// インストールしたリリースのバージョンを記録するファイル。
+/// `.aviutl2-version` は `latest` や `path:...` などの設定の値なので、リリース一覧との照合にはこちらを使います。
+pub const RELEASE_FILE: &str = ".aviutl2-release";
+
+#[derive(Serialize)]
+struct ReleaseEntry {
+    version: String,
+    date: Option<String>,
+    notes: Option<String>,
+    installed: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct InstalledEntry {
+    location: &'static str,
+    version: String,
+    path: String,
+}
+
+pub fn list(json: bool) -> Result<()> {
+    let config = load_config_if_exists()?;
+    let api_bases = super::prepare::api_bases(config.as_ref().and_then(|c| c.development.as_ref()));
+    let releases = fetch_releases(&api_bases)?;
+    let installed = installed_versions(config.as_ref())?;
+    let entries = releases
+        .into_iter()
+        .map(|release| {
+            let locations = installed
+                .iter()
+                .filter(|entry| entry.version == release.version)
+                .map(|entry| entry.location)
+                .collect();
+            ReleaseEntry {
+                version: release.version,
+                date: release.date,
+                notes: release.notes,
+                installed: locations,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    for entry in &entries {
+        let mut line = entry.version.clone();
+        if let Some(date) = &entry.date {
+            line.push_str(&format!("  {date}"));
+        }
+        if !entry.installed.is_empty() {
+            line.push_str(&format!("  [{}]", entry.installed.join(", ")));
+        }
+        println!("{line}");
+        if let Some(summary) = entry
+            .notes
+            .as_deref()
+            .and_then(|notes| notes.lines().find(|line| !line.trim().is_empty()))
+        {
+            println!("    {}", summary.trim());
+        }
+    }
+    Ok(())
+}
+
+pub fn installed(json: bool) -> Result<()> {
+    let config = load_config_if_exists()?;
+    let entries = installed_versions(config.as_ref())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        log::info!("インストール済みの AviUtl2 はありません");
+    }
+    for entry in &entries {
+        println!("{:<12} {}  ({})", entry.location, entry.version, entry.path);
+    }
+    Ok(())
+}
+
+/// API からリリース一覧を取得します。ミラーは前から順に試します。
+fn fetch_releases(api_bases: &[String]) -> Result<Vec<ApiRelease>> {
+    let mut last_error = None;
+    for (i, api_base) in api_bases.iter().enumerate() {
+        match fetch_json::<Vec<ApiRelease>>(&format!("{api_base}/versions"), &[]) {
+            Ok(releases) => return Ok(releases),
+            Err(err) => {
+                if i + 1 < api_bases.len() {
+                    log::warn!(
+                        "リリース一覧の取得に失敗したため次のミラーを試します: {}: {:#}",
+                        api_base,
+                        err
+                    );
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("AviUtl2 の API が指定されていません")))
+}
+
+/// 最新のリリースのバージョンを返します。
+pub fn latest_release(api_bases: &[String]) -> Result<String> {
+    fetch_releases(api_bases)?
+        .into_iter()
+        .next()
+        .map(|release| release.version)
+        .context("リリース一覧が空です")
+}
+
+fn installed_versions(config: Option<&Config>) -> Result<Vec<InstalledEntry>> {
+    let mut entries = Vec::new();
+    let dev_dir = development_dir(config.and_then(|c| c.development.as_ref()))?;
+    if let Some(entry) = read_install(&dev_dir, "development") {
+        entries.push(entry);
+    }
+    let preview_dir = preview_dir(config.and_then(|c| c.preview.as_ref()))?;
+    if let Some(entry) = read_install(&preview_dir, "preview") {
+        entries.push(entry);
+    }
+
+    let cache_dir = aviutl2_cache_dir()?;
+    if cache_dir.exists() {
+        let mut cached = Vec::new();
+        for entry in fs::read_dir(&cache_dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if let Some(version) = name
+                .strip_prefix("aviutl2-")
+                .and_then(|name| name.strip_suffix(".zip"))
+            {
+                cached.push(InstalledEntry {
+                    location: "cache",
+                    version: version.to_string(),
+                    path: path.display().to_string(),
+                });
+            }
+        }
+        cached.sort_by(|a, b| a.version.cmp(&b.version));
+        entries.extend(cached);
+    }
+    Ok(entries)
+}
+
+fn read_install(install_dir: &Path, location: &'static str) -> Option<InstalledEntry> {
+    let version = match fs::read_to_string(install_dir.join(RELEASE_FILE)) {
+        Ok(version) => version.trim().to_string(),
+        Err(_) => describe_fingerprint(
+            fs::read_to_string(install_dir.join(".aviutl2-version"))
+                .ok()?
+                .trim(),
+        ),
+    };
+    Some(InstalledEntry {
+        location,
+        version,
+        path: install_dir.display().to_string(),
+    })
+}
+
+/// `.aviutl2-version` の値を表示用に変換します。
+/// ローカルの zip やディレクトリはハッシュを含む内部的な値なので、元のパスだけを表示します。
+fn describe_fingerprint(fingerprint: &str) -> String {
+    let local = fingerprint
+        .strip_prefix("path:")
+        .or_else(|| fingerprint.strip_prefix("dir:"))
+        .and_then(|rest| rest.rsplit_once(':'))
+        .map(|(path, _hash)| path);
+    match local {
+        Some(path) => format!("ローカル ({path})"),
+        None => fingerprint.to_string(),
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{Result, bail};
+use fs_err as fs;
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+use super::develop::plan_artifacts;
+use crate::cache::{self, CacheIndex};
+use crate::config::{ArtifactSource, BUILTIN_PROFILES, load_config, parse_duration};
+use crate::util::{aviutl2_cache_dir, cache_dir, is_http_url};
+
+#[derive(Serialize)]
+struct ListEntry {
+    kind: &'static str,
+    source: String,
+    path: String,
+    size: u64,
+    /// 最後に使用した時刻（UNIX 秒）
+    last_used: u64,
+}
+
+pub fn list(json: bool) -> Result<()> {
+    let cache_dir = cache_dir()?;
+    let mut entries = Vec::new();
+    if cache_dir.exists() {
+        let index = CacheIndex::load(&cache_dir)?;
+        for (url, entry) in index.entries {
+            entries.push(ListEntry {
+                kind: "http",
+                source: url,
+                path: cache_dir.join(&entry.path).display().to_string(),
+                size: entry.size,
+                last_used: entry.last_used,
+            });
+        }
+    }
+    let aviutl2_dir = aviutl2_cache_dir()?;
+    if aviutl2_dir.exists() {
+        for entry in fs::read_dir(&aviutl2_dir)? {
+            let path = entry?.path();
+            let Some(version) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|name| name.strip_prefix("aviutl2-"))
+                .and_then(|name| name.strip_suffix(".zip"))
+            else {
+                continue;
+            };
+            entries.push(ListEntry {
+                kind: "aviutl2",
+                source: version.to_string(),
+                size: fs::metadata(&path)?.len(),
+                last_used: cache::modified_secs(&path),
+                path: path.display().to_string(),
+            });
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        log::info!("キャッシュはありません");
+        return Ok(());
+    }
+    let total: u64 = entries.iter().map(|entry| entry.size).sum();
+    for entry in &entries {
+        println!(
+            "{:<8} {:>10}  {}  {}",
+            entry.kind,
+            HumanBytes(entry.size).to_string(),
+            format_time(entry.last_used),
+            entry.source
+        );
+    }
+    println!("合計: {} 件, {}", entries.len(), HumanBytes(total));
+    Ok(())
+}
+
+pub fn prune(older_than: &str) -> Result<()> {
+    let Some(max_age) = parse_duration(older_than) else {
+        bail!(
+            "期間の形式が正しくありません（例: 30d, 12h）: {}",
+            older_than
+        );
+    };
+    let removed = cache::prune(max_age, &config_urls())?;
+    log::info!(
+        "{} 件のファイルを削除しました（{}）",
+        removed.files,
+        HumanBytes(removed.bytes)
+    );
+    Ok(())
+}
+
+/// 設定ファイルのいずれかのプロファイルで使われる HTTP の source の URL
+fn config_urls() -> Vec<String> {
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            log::debug!(
+                "設定ファイルを読み込めないため source の URL を調べません: {:#}",
+                err
+            );
+            return Vec::new();
+        }
+    };
+    let mut profiles = BUILTIN_PROFILES.map(str::to_string).to_vec();
+    profiles.extend(
+        config
+            .profiles
+            .iter()
+            .flatten()
+            .map(|(name, _)| name.clone()),
+    );
+    let mut urls = Vec::new();
+    for profile in profiles {
+        let planned = config
+            .profile(&profile)
+            .and_then(|profile| plan_artifacts(&config, Some(&profile), None));
+        for artifact in planned.into_iter().flatten() {
+            match artifact.source {
+                ArtifactSource::Path(url) if is_http_url(&url) => urls.push(url),
+                ArtifactSource::Url(source) => urls.push(source.url),
+                _ => {}
+            }
+        }
+    }
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+pub fn clean() -> Result<()> {
+    let removed = cache::clean()?;
+    log::info!(
+        "キャッシュを削除しました: {} 件（{}）",
+        removed.files,
+        HumanBytes(removed.bytes)
+    );
+    Ok(())
+}
+
+fn format_time(unix: u64) -> String {
+    let Ok(format) = time::format_description::parse("[year]-[month]-[day] [hour]:[minute]") else {
+        return "-".to_string();
+    };
+    time::OffsetDateTime::from_unix_timestamp(unix as i64)
+        .ok()
+        .and_then(|time| time.format(&format).ok())
+        .unwrap_or_else(|| "-".to_string())
+}
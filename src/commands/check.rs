@@ -0,0 +1,581 @@
+use anyhow::{Result, anyhow, bail};
+use fs_err as fs;
+use jsonschema::error::{TypeKind, ValidationErrorKind};
+use jsonschema::paths::LocationSegment;
+use jsonschema::{JsonType, ValidationError};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+use toml_edit::{Document, Item};
+
+use crate::config::{
+    BUILTIN_PROFILES, BuildCommand, Config, SCHEMA_VERSION, find_config_path, local_config_path,
+    read_layered_config, resolve_project_version,
+};
+use crate::schema::{CONFIG_SCHEMA_JSON, Segment, key_path, unknown_keys};
+
+/// 配置先の重複。同じ成果物の組み合わせは、重複するプロファイルをまとめて報告します。
+struct DestinationCollision<'a> {
+    name: &'a str,
+    first: &'a str,
+    /// 配置先を上書きしたプロファイル（`None` なら成果物の `destination`）
+    overridden_by: Option<String>,
+    destination: String,
+    profiles: Vec<&'a str>,
+}
+
+/// 設定ファイルの問題
+struct Problem {
+    /// 問題のあるキーのパス
+    path: Vec<Segment>,
+    message: String,
+    /// 問題のあるファイル（`None` の場合はキーのパスから探す）
+    file: Option<usize>,
+}
+
+impl Problem {
+    fn new(path: Vec<Segment>, message: String) -> Self {
+        Self {
+            path,
+            message,
+            file: None,
+        }
+    }
+}
+
+/// 検証する設定ファイル
+struct SourceFile {
+    path: PathBuf,
+    content: String,
+    document: Document<String>,
+}
+
+impl SourceFile {
+    fn parse(path: PathBuf) -> Result<Self> {
+        let content = fs::read_to_string(&path)?;
+        match Document::parse(content.clone()) {
+            Ok(document) => Ok(Self {
+                path,
+                content,
+                document,
+            }),
+            Err(err) => {
+                let (line, column) = line_column(&content, err.span().map_or(0, |span| span.start));
+                bail!(
+                    "{}:{}:{}: TOML の構文が正しくありません: {}",
+                    path.display(),
+                    line,
+                    column,
+                    err.message()
+                );
+            }
+        }
+    }
+}
+
+pub fn run() -> Result<()> {
+    let path = find_config_path()?;
+    let local_path = local_config_path(&path);
+    let mut files = vec![SourceFile::parse(path.clone())?];
+    if local_path.is_file() {
+        files.push(SourceFile::parse(local_path)?);
+    }
+
+    // 不明なキーはファイルごとに、それ以外はローカルの設定ファイルを重ねた内容で確認する
+    let layered = read_layered_config(&path)?;
+    let mut problems = Vec::new();
+    for (index, (_, table)) in layered.layers.iter().enumerate() {
+        let instance = serde_json::to_value(table)?;
+        problems.extend(unknown_keys(&instance).into_iter().map(|key| {
+            let message = key.message();
+            Problem {
+                path: key.path,
+                message,
+                file: Some(index),
+            }
+        }));
+    }
+    let instance = serde_json::to_value(&layered.table)?;
+    let schema_problems = schema_problems(&instance)?;
+    // スキーマに沿っていない場合は Config に読み込めないので、意味のチェックは行わない
+    if schema_problems.is_empty() {
+        let mut table = layered.table;
+        match resolve_project_version(&mut table, &path) {
+            Ok(()) => match Config::deserialize(toml::Value::Table(table)) {
+                Ok(config) => problems.extend(semantic_problems(&config)),
+                Err(err) => problems.push(Problem::new(Vec::new(), err.message().to_string())),
+            },
+            Err(err) => problems.push(Problem::new(
+                vec![
+                    Segment::Key("project".to_string()),
+                    Segment::Key("version".to_string()),
+                ],
+                format!("{:#}", err),
+            )),
+        }
+    }
+    problems.extend(schema_problems);
+
+    let mut located = problems
+        .iter()
+        .map(|problem| {
+            let (file, span) = match problem.file {
+                Some(file) => (
+                    file,
+                    find_span(files[file].document.as_item(), &problem.path).0,
+                ),
+                None => locate(&files, &problem.path),
+            };
+            let offset = span.map_or(0, |span| span.start);
+            ((file, line_column(&files[file].content, offset)), problem)
+        })
+        .collect::<Vec<_>>();
+    located.sort_by_key(|(position, _)| *position);
+    for ((file, (line, column)), problem) in &located {
+        println!(
+            "{}:{}:{}: {}: {}",
+            files[*file].path.display(),
+            line,
+            column,
+            key_path(&problem.path),
+            problem.message
+        );
+    }
+    if !located.is_empty() {
+        bail!("設定ファイルに {} 件の問題があります", located.len());
+    }
+    log::info!("設定ファイルに問題はありません: {}", path.display());
+    Ok(())
+}
+
+/// 後から重ねたファイルを優先して、キーのパスが最後まで見つかるファイルとその位置を返します。
+fn locate(files: &[SourceFile], path: &[Segment]) -> (usize, Option<Range<usize>>) {
+    for (index, file) in files.iter().enumerate().rev() {
+        if let (span, true) = find_span(file.document.as_item(), path) {
+            return (index, span);
+        }
+    }
+    (0, find_span(files[0].document.as_item(), path).0)
+}
+
+fn schema_problems(instance: &serde_json::Value) -> Result<Vec<Problem>> {
+    let schema: serde_json::Value = serde_json::from_str(CONFIG_SCHEMA_JSON)?;
+    let validator = jsonschema::validator_for(&schema)
+        .map_err(|err| anyhow!("JSON Schema の読み込みに失敗しました: {err}"))?;
+    let mut problems = Vec::new();
+    for error in validator.iter_errors(instance) {
+        collect_schema_problems(&error, &mut problems);
+    }
+    Ok(problems)
+}
+
+/// anyOf のエラーは「どの候補にも一致しない」としか言わないので、候補のうち最も近いもののエラーを報告します。
+fn collect_schema_problems(error: &ValidationError, problems: &mut Vec<Problem>) {
+    if let ValidationErrorKind::AnyOf { context } | ValidationErrorKind::OneOfNotValid { context } =
+        error.kind()
+    {
+        let depth = error.instance_path().segments().count();
+        // 型が違う候補は除き、値の中まで一致した候補があればそれを優先する
+        let candidates = context
+            .iter()
+            .filter(|errors| {
+                !errors.iter().any(|error| {
+                    matches!(error.kind(), ValidationErrorKind::Type { .. })
+                        && error.instance_path().segments().count() == depth
+                })
+            })
+            .collect::<Vec<_>>();
+        let deeper = candidates
+            .iter()
+            .filter(|errors| {
+                errors
+                    .iter()
+                    .any(|error| error.instance_path().segments().count() > depth)
+            })
+            .min_by_key(|errors| errors.len());
+        let best = match (deeper, candidates.as_slice()) {
+            (Some(errors), _) => Some(errors),
+            (None, [errors]) => Some(errors),
+            _ => None,
+        };
+        if let Some(errors) = best {
+            for error in errors.iter() {
+                collect_schema_problems(error, problems);
+            }
+            return;
+        }
+        // どの候補とも型が違う場合は、受け付ける型をまとめて示す
+        if candidates.is_empty() {
+            let mut expected = Vec::new();
+            for error in context.iter().flatten() {
+                if let ValidationErrorKind::Type { kind } = error.kind() {
+                    for name in type_names(kind) {
+                        if !expected.contains(&name) {
+                            expected.push(name);
+                        }
+                    }
+                }
+            }
+            problems.push(Problem::new(
+                instance_path(error),
+                format!("{}である必要があります", expected.join(" / ")),
+            ));
+            return;
+        }
+    }
+    problems.push(Problem::new(instance_path(error), schema_message(error)));
+}
+
+fn instance_path(error: &ValidationError) -> Vec<Segment> {
+    error
+        .instance_path()
+        .segments()
+        .map(|segment| match segment {
+            LocationSegment::Property(key) => Segment::Key(key.into_owned()),
+            LocationSegment::Index(index) => Segment::Index(index),
+        })
+        .collect()
+}
+
+fn schema_message(error: &ValidationError) -> String {
+    match error.kind() {
+        ValidationErrorKind::Type { kind } => {
+            format!("{}である必要があります", type_names(kind).join(" / "))
+        }
+        ValidationErrorKind::Required { property } => {
+            format!("{} が必要です", property.as_str().unwrap_or_default())
+        }
+        ValidationErrorKind::Enum { options } => format!(
+            "{} のいずれかである必要があります",
+            options
+                .as_array()
+                .map(|options| {
+                    options
+                        .iter()
+                        .map(|option| option.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_else(|| options.to_string())
+        ),
+        ValidationErrorKind::Constant { expected_value } => {
+            format!("{} である必要があります", expected_value)
+        }
+        ValidationErrorKind::Pattern { pattern } => format!("{} に一致しません", pattern),
+        ValidationErrorKind::AnyOf { .. } | ValidationErrorKind::OneOfNotValid { .. } => {
+            "どの形式にも一致しません".to_string()
+        }
+        _ => error.to_string(),
+    }
+}
+
+fn type_names(kind: &TypeKind) -> Vec<&'static str> {
+    match kind {
+        TypeKind::Single(ty) => vec![type_name(*ty)],
+        TypeKind::Multiple(types) => types.iter().map(type_name).collect(),
+    }
+}
+
+fn type_name(ty: JsonType) -> &'static str {
+    match ty {
+        JsonType::String => "文字列",
+        JsonType::Boolean => "真偽値",
+        JsonType::Integer => "整数",
+        JsonType::Number => "数値",
+        JsonType::Array => "配列",
+        JsonType::Object => "テーブル",
+        JsonType::Null => "null",
+    }
+}
+
+/// スキーマでは表現できない、設定同士の整合性を確認します。
+fn semantic_problems(config: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if let Some(version) = config.schema_version {
+        if version > SCHEMA_VERSION {
+            problems.push(Problem::new(
+                keys(&["schema_version"]),
+                format!(
+                    "この au2 が対応している形式（{}）より新しい形式です。au2 を更新してください",
+                    SCHEMA_VERSION
+                ),
+            ));
+        } else if version < SCHEMA_VERSION {
+            problems.push(Problem::new(
+                keys(&["schema_version"]),
+                "古い形式です（`au2 migrate` で更新できます）".to_string(),
+            ));
+        }
+    }
+    let groups = config.build_group.as_ref();
+    let mut check_build = |path: Vec<Segment>, command: Option<&BuildCommand>| {
+        if let Some(BuildCommand::Group(group_ref)) = command
+            && !groups.is_some_and(|groups| groups.contains_key(&group_ref.group))
+        {
+            let mut path = path;
+            path.push(Segment::Key("group".to_string()));
+            problems.push(Problem::new(
+                path,
+                format!("build_group.{} が定義されていません", group_ref.group),
+            ));
+        }
+    };
+
+    if let Some(groups) = groups {
+        for (name, command) in sorted(groups) {
+            check_build(keys(&["build_group", name]), Some(command));
+        }
+    }
+    for (name, artifact) in sorted(&config.artifacts) {
+        check_build(keys(&["artifacts", name, "build"]), artifact.build.as_ref());
+        for (profile_name, profile) in artifact.profiles.iter().flat_map(sorted) {
+            check_build(
+                keys(&["artifacts", name, "profiles", profile_name, "build"]),
+                profile.build.as_ref(),
+            );
+        }
+    }
+    let hooks = [
+        (
+            "development",
+            config
+                .development
+                .as_ref()
+                .map(|d| (d.prebuild.as_ref(), d.postbuild.as_ref())),
+        ),
+        (
+            "preview",
+            config
+                .preview
+                .as_ref()
+                .map(|p| (p.prebuild.as_ref(), p.postbuild.as_ref())),
+        ),
+        (
+            "release",
+            config
+                .release
+                .as_ref()
+                .map(|r| (r.prebuild.as_ref(), r.postbuild.as_ref())),
+        ),
+    ];
+    for (section, hooks) in hooks {
+        if let Some((prebuild, postbuild)) = hooks {
+            check_build(keys(&[section, "prebuild"]), prebuild);
+            check_build(keys(&[section, "postbuild"]), postbuild);
+        }
+    }
+
+    let mut includes = vec![
+        (
+            keys(&["preview", "include"]),
+            config.preview.as_ref().and_then(|p| p.include.as_ref()),
+        ),
+        (
+            keys(&["release", "include"]),
+            config.release.as_ref().and_then(|r| r.include.as_ref()),
+        ),
+    ];
+    for (name, profile) in config.profiles.iter().flat_map(sorted) {
+        includes.push((
+            keys(&["profiles", name, "include"]),
+            profile.include.as_ref(),
+        ));
+    }
+    for (include_path, include) in includes {
+        for (index, name) in include.into_iter().flatten().enumerate() {
+            if !config.artifacts.contains_key(name) {
+                let mut path = include_path.clone();
+                path.push(Segment::Index(index));
+                problems.push(Problem::new(
+                    path,
+                    format!("artifacts.{} が定義されていません", name),
+                ));
+            }
+        }
+    }
+
+    for (name, profile) in config.profiles.iter().flat_map(sorted) {
+        let Some(parent) = &profile.inherits else {
+            continue;
+        };
+        let message = if config.profile_definition(parent).is_none() {
+            format!("profiles.{} が定義されていません", parent)
+        } else if let Err(err) = config.profile(name) {
+            format!("{:#}", err)
+        } else {
+            continue;
+        };
+        problems.push(Problem::new(keys(&["profiles", name, "inherits"]), message));
+    }
+    let selected = [
+        (
+            "development",
+            config.development.as_ref().and_then(|d| d.profile.as_ref()),
+        ),
+        (
+            "preview",
+            config.preview.as_ref().and_then(|p| p.profile.as_ref()),
+        ),
+        (
+            "release",
+            config.release.as_ref().and_then(|r| r.profile.as_ref()),
+        ),
+    ];
+    for (section, profile) in selected {
+        if let Some(profile) = profile
+            && config.profile_definition(profile).is_none()
+        {
+            problems.push(Problem::new(
+                keys(&[section, "profile"]),
+                format!("profiles.{} が定義されていません", profile),
+            ));
+        }
+    }
+    for (name, artifact) in sorted(&config.artifacts) {
+        for (profile, _) in artifact.profiles.iter().flat_map(sorted) {
+            if config.profile_definition(profile).is_none() {
+                problems.push(Problem::new(
+                    keys(&["artifacts", name, "profiles", profile]),
+                    format!("profiles.{} が定義されていません", profile),
+                ));
+            }
+        }
+    }
+
+    // 配置先はプロファイルで上書きできるので、プロファイルごとに重複を確認する
+    let mut profile_names = BUILTIN_PROFILES.to_vec();
+    for (name, _) in config.profiles.iter().flat_map(sorted) {
+        if !profile_names.contains(&name) {
+            profile_names.push(name);
+        }
+    }
+    // 継承の問題は上で報告している
+    let profiles = profile_names
+        .into_iter()
+        .filter_map(|name| config.profile(name).ok())
+        .collect::<Vec<_>>();
+    let mut collisions: Vec<DestinationCollision> = Vec::new();
+    for profile in &profiles {
+        let mut destinations = HashMap::new();
+        for (name, artifact) in sorted(&config.artifacts) {
+            let Ok(resolved) = artifact.with_profile(Some(profile)) else {
+                continue;
+            };
+            if !resolved.enabled.or(profile.enabled).unwrap_or(true) {
+                continue;
+            }
+            let destination = resolved
+                .destination
+                .replace('\\', "/")
+                .trim_end_matches('/')
+                .to_string();
+            let Some(first) = destinations.insert(destination, name) else {
+                continue;
+            };
+            let overridden_by = profile
+                .chain
+                .iter()
+                .find(|chain| {
+                    artifact
+                        .profiles
+                        .as_ref()
+                        .and_then(|overrides| overrides.get(*chain))
+                        .is_some_and(|overrides| overrides.destination.is_some())
+                })
+                .cloned();
+            match collisions.iter_mut().find(|collision| {
+                collision.name == name
+                    && collision.first == first
+                    && collision.overridden_by == overridden_by
+            }) {
+                Some(collision) => collision.profiles.push(&profile.name),
+                None => collisions.push(DestinationCollision {
+                    name,
+                    first,
+                    overridden_by,
+                    destination: resolved.destination.clone(),
+                    profiles: vec![&profile.name],
+                }),
+            }
+        }
+    }
+    for DestinationCollision {
+        name,
+        first,
+        overridden_by,
+        destination,
+        profiles: in_profiles,
+    } in collisions
+    {
+        let path = match &overridden_by {
+            Some(profile) => keys(&["artifacts", name, "profiles", profile, "destination"]),
+            None => keys(&["artifacts", name, "destination"]),
+        };
+        let message = if in_profiles.len() == profiles.len() {
+            format!(
+                "artifacts.{} と配置先が重複しています: {}",
+                first, destination
+            )
+        } else {
+            format!(
+                "プロファイル {} で artifacts.{} と配置先が重複しています: {}",
+                in_profiles.join(", "),
+                first,
+                destination
+            )
+        };
+        problems.push(Problem::new(path, message));
+    }
+    problems
+}
+
+fn sorted<V>(map: &HashMap<String, V>) -> Vec<(&str, &V)> {
+    let mut entries = map
+        .iter()
+        .map(|(key, value)| (key.as_str(), value))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(key, _)| *key);
+    entries
+}
+
+fn keys(path: &[&str]) -> Vec<Segment> {
+    path.iter()
+        .map(|key| Segment::Key(key.to_string()))
+        .collect()
+}
+
+/// キーのパスをたどり、最も深く見つかったキー（または値）の位置と、最後までたどれたかを返します。
+fn find_span(root: &Item, path: &[Segment]) -> (Option<Range<usize>>, bool) {
+    let mut item = root;
+    let mut span = None;
+    for segment in path {
+        let (next, key_span) = match segment {
+            Segment::Key(key) => (
+                item.get(key.as_str()),
+                item.as_table_like()
+                    .and_then(|table| table.key(key))
+                    .and_then(|key| key.span()),
+            ),
+            Segment::Index(index) => (item.get(*index), None),
+        };
+        let Some(next) = next else {
+            return (span, false);
+        };
+        span = key_span.or_else(|| next.span()).or(span);
+        item = next;
+    }
+    (span, true)
+}
+
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit_once('\n')
+        .map_or(before, |(_, last)| last)
+        .chars()
+        .count()
+        + 1;
+    (line, column)
+}
@@ -0,0 +1,240 @@
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::cli::ConfigTarget;
+use crate::config::{
+    ArtifactSource, Config, LayeredConfig, PlacementMethod, find_config_path, load_config,
+    read_layered_config,
+};
+use crate::template::TemplateContext;
+use crate::util::{development_dir, is_http_url, preview_dir};
+
+/// コマンドで実際に使われる設定
+#[derive(Serialize)]
+struct ResolvedSettings {
+    command: &'static str,
+    profile: String,
+    /// 継承元のプロファイル（近い順）
+    inherits: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    install_dir: Option<String>,
+    /// 作成するリリースパッケージ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<String>,
+    /// カタログの download_source で使うファイル名の正規表現
+    #[serde(skip_serializing_if = "Option::is_none")]
+    catalog_pattern: Option<String>,
+    prebuild: Vec<String>,
+    postbuild: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    artifacts: Vec<ResolvedArtifactSettings>,
+}
+
+#[derive(Serialize)]
+struct ResolvedArtifactSettings {
+    name: String,
+    source: toml::Value,
+    destination: String,
+    placement_method: PlacementMethod,
+    build: Vec<String>,
+}
+
+/// ローカルの設定ファイルを重ねた後の設定を、値ごとにどのファイルから来たかをコメントで示して表示します。
+pub fn show_origin() -> Result<()> {
+    let path = find_config_path()?;
+    let layered = read_layered_config(&path)?;
+    let mut document = toml::to_string(&layered.table)?.parse::<DocumentMut>()?;
+    annotate_table(document.as_table_mut(), &mut Vec::new(), &layered);
+    print!("{}", document);
+    Ok(())
+}
+
+fn annotate_table(table: &mut Table, path: &mut Vec<String>, layered: &LayeredConfig) {
+    for (key, item) in table.iter_mut() {
+        path.push(key.get().to_string());
+        let origin = layered
+            .origin(path)
+            .map(|origin| origin.display().to_string());
+        match item {
+            Item::Table(table) => annotate_table(table, path, layered),
+            Item::Value(value) => {
+                if let Some(origin) = origin {
+                    value.decor_mut().set_suffix(format!("  # {origin}"));
+                }
+            }
+            Item::ArrayOfTables(array) => {
+                if let Some(origin) = origin {
+                    for table in array.iter_mut() {
+                        table.decor_mut().set_prefix(format!("\n# {origin}\n"));
+                    }
+                }
+            }
+            Item::None => {}
+        }
+        path.pop();
+    }
+}
+
+/// プロファイルや include、ビルドグループを解決し、コマンドで実際に使われる設定を表示します。
+/// source の取得やビルドは行わないので、URL や git などの source は指定されたまま表示します。
+pub fn show_resolved(
+    profile: Option<String>,
+    command: Option<ConfigTarget>,
+    json: bool,
+) -> Result<()> {
+    let config = load_config()?;
+    let command = command.unwrap_or(ConfigTarget::Develop);
+    let (profile, include, hooks, install_dir) = match command {
+        ConfigTarget::Develop => {
+            let dev = config
+                .development
+                .as_ref()
+                .context("development 設定が必要です")?;
+            (
+                super::develop::profile_name(&config, profile),
+                None,
+                (dev.prebuild.as_ref(), dev.postbuild.as_ref()),
+                Some(development_dir(Some(dev))?),
+            )
+        }
+        ConfigTarget::Preview => {
+            let preview = config.preview.as_ref().context("preview 設定が必要です")?;
+            let release = config.release.as_ref().context("release 設定が必要です")?;
+            (
+                super::preview::profile_name(&config, profile),
+                preview.include.as_deref().or(release.include.as_deref()),
+                (preview.prebuild.as_ref(), preview.postbuild.as_ref()),
+                Some(preview_dir(Some(preview))?),
+            )
+        }
+        ConfigTarget::Release => {
+            let release = config.release.as_ref().context("release 設定が必要です")?;
+            (
+                super::release::profile_name(&config, profile),
+                release.include.as_deref(),
+                (release.prebuild.as_ref(), release.postbuild.as_ref()),
+                None,
+            )
+        }
+    };
+    let profile = config.profile(&profile)?;
+    let context = TemplateContext::project(&config.project).with_profile(Some(&profile.name));
+    let groups = config.build_group.as_ref();
+
+    let mut settings = ResolvedSettings {
+        command: match command {
+            ConfigTarget::Develop => "develop",
+            ConfigTarget::Preview => "preview",
+            ConfigTarget::Release => "release",
+        },
+        profile: profile.name.clone(),
+        inherits: profile.chain[1..].to_vec(),
+        install_dir: install_dir.map(|dir| dir.display().to_string()),
+        package: None,
+        catalog_pattern: None,
+        prebuild: super::develop::resolve_build_commands(hooks.0, groups, &context)?,
+        postbuild: super::develop::resolve_build_commands(hooks.1, groups, &context)?,
+        env: profile.env.clone(),
+        artifacts: Vec::new(),
+    };
+    if let (ConfigTarget::Release, Some(release)) = (command, &config.release) {
+        let package = super::release::output_dir(release)?
+            .join(super::release::zip_file_name(release, &context)?);
+        settings.package = Some(package.display().to_string());
+        settings.catalog_pattern = config
+            .catalog
+            .as_ref()
+            .map(|_| {
+                super::release::generate_au2pkg_pattern(&config.project, release, &profile.name)
+            })
+            .transpose()?;
+    }
+    for artifact in super::develop::plan_artifacts(&config, Some(&profile), include)? {
+        let mut visiting = Vec::new();
+        let Some(source) = resolved_source(
+            &config,
+            Path::new(""),
+            &profile.name,
+            &artifact.name,
+            &artifact.source,
+            &mut visiting,
+        )?
+        else {
+            continue;
+        };
+        let build =
+            super::develop::resolve_build_commands(artifact.build.as_ref(), groups, &context)
+                .with_context(|| {
+                    format!("artifacts.{}.build の解決に失敗しました", artifact.name)
+                })?;
+        settings.artifacts.push(ResolvedArtifactSettings {
+            name: artifact.name,
+            source,
+            destination: artifact.destination,
+            placement_method: artifact.placement_method,
+            build,
+        });
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&settings)?);
+    } else {
+        print!("{}", toml::to_string(&settings)?);
+    }
+    Ok(())
+}
+
+/// 配置する source を求めます。ローカルのパスと cargo、別プロジェクトの成果物はパスに解決し、
+/// 取得が必要な source は指定されたまま返します。参照先の成果物が無効なら None を返します。
+fn resolved_source(
+    config: &Config,
+    root: &Path,
+    profile: &str,
+    name: &str,
+    source: &ArtifactSource,
+    visiting: &mut Vec<String>,
+) -> Result<Option<toml::Value>> {
+    let path = match source {
+        ArtifactSource::Path(path) if !is_http_url(path) => root.join(path),
+        ArtifactSource::Cargo(cargo) => {
+            let profile = config.profile(profile)?;
+            crate::cargo::locate(cargo, Some(&profile), root)
+                .with_context(|| format!("artifacts.{} の解決に失敗しました", name))?
+                .output
+        }
+        ArtifactSource::Project(dependency) => {
+            let (dependency_root, dependency_config) =
+                super::develop::load_dependency(root, name, dependency)?;
+            let key = format!("{}#{}", dependency_root.display(), dependency.artifact);
+            if visiting.contains(&key) {
+                bail!("project の循環参照を検出しました: {}", key);
+            }
+            let dependency_profile = dependency_config.profile(profile)?;
+            let planned = super::develop::plan_artifacts(
+                &dependency_config,
+                Some(&dependency_profile),
+                Some(std::slice::from_ref(&dependency.artifact)),
+            )?;
+            let Some(planned) = planned.into_iter().next() else {
+                return Ok(None);
+            };
+            visiting.push(key);
+            let resolved = resolved_source(
+                &dependency_config,
+                &dependency_root,
+                profile,
+                &planned.name,
+                &planned.source,
+                visiting,
+            )?;
+            visiting.pop();
+            return Ok(resolved);
+        }
+        _ => return Ok(Some(toml::Value::try_from(source.redacted())?)),
+    };
+    Ok(Some(toml::Value::String(path.display().to_string())))
+}
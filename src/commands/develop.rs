@@ -1,11 +1,18 @@
 use anyhow::{Context, Result, bail};
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use crate::config::load_config;
-use crate::config::{BuildCommand, Config, PlacementMethod};
-use crate::util::{copy_to_destination, development_dir, find_aviutl2_data_dir, resolve_source};
+use crate::cargo::CargoBuild;
+use crate::config::{
+    ArtifactSource, Aviutl2Version, BuildCommand, Config, PlacementMethod, ProjectSource,
+    ResolvedProfile, config_file_in, load_config, read_config,
+};
+use crate::pe;
+use crate::template::{TemplateContext, expand, expand_all};
+use crate::util::{
+    ResolvedSource, copy_to_destination, development_dir, find_aviutl2_data_dir, resolve_source,
+};
 
 pub struct ResolvedArtifact {
     pub source: PathBuf,
@@ -14,9 +21,24 @@ pub struct ResolvedArtifact {
     pub placement_method: PlacementMethod,
 }
 
+/// プロファイルを反映した成果物の設定。source と destination は展開済みです。
+pub struct PlannedArtifact {
+    pub name: String,
+    pub source: ArtifactSource,
+    pub destination: String,
+    pub build: Option<BuildCommand>,
+    pub placement_method: PlacementMethod,
+}
+
 pub struct ResolvedBuild {
     pub commands: Vec<String>,
     pub group: Option<String>,
+    /// 別プロジェクトの成果物をビルドする場合の作業ディレクトリ
+    pub working_dir: Option<PathBuf>,
+    /// build が指定されていない cargo の source で自動的に実行するビルド
+    pub cargo: Option<CargoBuild>,
+    /// プロファイルで指定された環境変数
+    pub env: BTreeMap<String, String>,
 }
 
 pub fn run(
@@ -31,18 +53,18 @@ pub fn run(
         .as_ref()
         .context("development 設定が必要です")?;
     warn_if_prepare_snapshot_changed(&config, &dev.aviutl2_version)?;
-    let install_dir = development_dir(dev)?;
-    let profile = profile
-        .as_deref()
-        .or(dev.profile.as_deref())
-        .unwrap_or("debug");
-    run_optional_commands(dev.prebuild.as_ref(), config.build_group.as_ref())?;
-    let artifacts = resolve_artifacts(&config, Some(profile), None, refresh)?;
+    let install_dir = development_dir(Some(dev))?;
+    let profile = config.profile(&profile_name(&config, profile))?;
+    run_optional_commands(dev.prebuild.as_ref(), &config, &profile)?;
+    let artifacts = resolve_artifacts(&config, Some(&profile.name), None, refresh)?;
     let data_dir = find_aviutl2_data_dir(&install_dir)?;
     let mut anything_copied = false;
     let mut executed_groups = HashSet::new();
     for artifact in artifacts {
         run_build_plan(&artifact.build_plan, &mut executed_groups)?;
+        if let Err(err) = pe::verify_plugin(&artifact.source, &artifact.destination) {
+            log::warn!("{:#}", err);
+        }
         let dest = data_dir.join(&artifact.destination);
         let needs_copy = matches!(artifact.placement_method, PlacementMethod::Copy);
         if needs_copy {
@@ -53,7 +75,7 @@ pub fn run(
     if anything_copied {
         log::info!("成果物を配置しました");
     }
-    run_optional_commands(dev.postbuild.as_ref(), config.build_group.as_ref())?;
+    run_optional_commands(dev.postbuild.as_ref(), &config, &profile)?;
 
     if !skip_start {
         let aviutl_exe = data_dir.parent().unwrap_or(&data_dir).join("aviutl2.exe");
@@ -70,18 +92,21 @@ pub fn run(
     Ok(())
 }
 
-fn warn_if_prepare_snapshot_changed(config: &Config, aviutl2_version: &str) -> Result<()> {
+/// 指定がなければ development.profile、それもなければ debug を使います。
+pub(crate) fn profile_name(config: &Config, profile: Option<String>) -> String {
+    profile
+        .or_else(|| config.development.as_ref()?.profile.clone())
+        .unwrap_or_else(|| "debug".to_string())
+}
+
+fn warn_if_prepare_snapshot_changed(
+    config: &Config,
+    aviutl2_version: &Aviutl2Version,
+) -> Result<()> {
     let Some(snapshot) = super::prepare::load_prepare_snapshot()? else {
         return Ok(());
     };
-    let mut ordered = std::collections::BTreeMap::new();
-    for (name, artifact) in &config.artifacts {
-        ordered.insert(name.clone(), artifact.clone());
-    }
-    let current = super::prepare::PrepareSnapshot {
-        aviutl2_version: aviutl2_version.to_string(),
-        artifacts: ordered,
-    };
+    let current = super::prepare::PrepareSnapshot::new(&config.artifacts, aviutl2_version);
     if snapshot.aviutl2_version != current.aviutl2_version
         || snapshot.artifacts != current.artifacts
     {
@@ -98,64 +123,260 @@ pub fn resolve_artifacts(
     include: Option<&[String]>,
     refresh: bool,
 ) -> Result<Vec<ResolvedArtifact>> {
-    let mut resolved = Vec::new();
-    for (name, artifact) in &config.artifacts {
+    let mut visiting = Vec::new();
+    resolve_artifacts_in(
+        config,
+        Path::new(""),
+        profile,
+        include,
+        refresh,
+        &mut visiting,
+    )
+}
+
+/// 使う成果物を名前順に選び、プロファイルによる上書きと `{name}` などの展開を反映します。
+/// source の取得やビルドは行いません。
+pub fn plan_artifacts(
+    config: &Config,
+    profile: Option<&ResolvedProfile>,
+    include: Option<&[String]>,
+) -> Result<Vec<PlannedArtifact>> {
+    let include = include.or(profile.and_then(|p| p.include.as_deref()));
+    let context =
+        TemplateContext::project(&config.project).with_profile(profile.map(|p| p.name.as_str()));
+    let mut names = config.artifacts.keys().collect::<Vec<_>>();
+    names.sort();
+    let mut planned = Vec::new();
+    for name in names {
         if let Some(include) = include
             && !include.iter().any(|item| item == name)
         {
             continue;
         }
-        let profile_data = profile.and_then(|p| {
-            artifact
-                .profiles
-                .as_ref()
-                .and_then(|profiles| profiles.get(p))
-        });
-        let enabled = profile_data
-            .and_then(|p| p.enabled)
-            .or(artifact.enabled)
+        let artifact = config.artifacts[name]
+            .with_profile(profile)
+            .with_context(|| format!("artifacts.{}.profiles の適用に失敗しました", name))?;
+        let enabled = artifact
+            .enabled
+            .or(profile.and_then(|p| p.enabled))
             .unwrap_or(true);
         if !enabled {
             continue;
         }
-        let source = profile_data
-            .and_then(|p| p.source.clone())
-            .or_else(|| artifact.source.clone())
+        let source = artifact
+            .source
+            .as_ref()
             .with_context(|| format!("artifacts.{}.source が必要です", name))?;
-        let source = resolve_source(&source, refresh)?;
-        let build = profile_data
-            .and_then(|p| p.build.clone())
-            .or_else(|| artifact.build.clone());
-        let build_plan = resolve_build_plan(build.as_ref(), config.build_group.as_ref())?;
-        let placement_method = artifact
-            .placement_method
-            .unwrap_or(PlacementMethod::Symlink);
-        resolved.push(ResolvedArtifact {
+        let source = expand_all(source, &context)
+            .with_context(|| format!("artifacts.{}.source の展開に失敗しました", name))?;
+        let destination = expand(&artifact.destination, &context)
+            .with_context(|| format!("artifacts.{}.destination の展開に失敗しました", name))?;
+        planned.push(PlannedArtifact {
+            name: name.clone(),
             source,
-            destination: PathBuf::from(&artifact.destination),
-            build_plan,
-            placement_method,
+            destination,
+            build: artifact.build,
+            placement_method: artifact
+                .placement_method
+                .unwrap_or(PlacementMethod::Symlink),
         });
     }
+    Ok(planned)
+}
+
+/// `root` は `config` のプロジェクトのディレクトリです。別プロジェクトの成果物を参照する場合に使います。
+fn resolve_artifacts_in(
+    config: &Config,
+    root: &Path,
+    profile: Option<&str>,
+    include: Option<&[String]>,
+    refresh: bool,
+    visiting: &mut Vec<String>,
+) -> Result<Vec<ResolvedArtifact>> {
+    let profile = profile.map(|name| config.profile(name)).transpose()?;
+    let profile_name = profile.as_ref().map(|p| p.name.as_str());
+    let context = TemplateContext::project(&config.project).with_profile(profile_name);
+    let env = profile.as_ref().map(|p| p.env.clone()).unwrap_or_default();
+    let mut resolved = Vec::new();
+    for planned in plan_artifacts(config, profile.as_ref(), include)? {
+        let PlannedArtifact {
+            name,
+            source,
+            destination: artifact_destination,
+            build,
+            placement_method,
+        } = planned;
+
+        if let ArtifactSource::Project(dependency) = &source {
+            let (dependency_root, dependency_config) = load_dependency(root, &name, dependency)?;
+            let key = format!("{}#{}", dependency_root.display(), dependency.artifact);
+            if visiting.contains(&key) {
+                bail!("project の循環参照を検出しました: {}", key);
+            }
+            visiting.push(key);
+            let dependency_artifacts = resolve_artifacts_in(
+                &dependency_config,
+                &dependency_root,
+                profile_name,
+                Some(std::slice::from_ref(&dependency.artifact)),
+                refresh,
+                visiting,
+            )?;
+            visiting.pop();
+            if dependency_artifacts.is_empty() {
+                log::warn!(
+                    "artifacts.{} が参照する {} の artifacts.{} は無効になっているため配置しません",
+                    name,
+                    dependency_root.display(),
+                    dependency.artifact
+                );
+                continue;
+            }
+            let dependency_profile = profile_name
+                .map(|name| dependency_config.profile(name))
+                .transpose()?;
+            let dependency_destination = expand(
+                &dependency_config.artifacts[&dependency.artifact]
+                    .with_profile(dependency_profile.as_ref())?
+                    .destination,
+                &TemplateContext::project(&dependency_config.project).with_profile(profile_name),
+            )?;
+            for dependency_artifact in dependency_artifacts {
+                // 参照先の destination からの相対位置を保ったまま、こちらの destination に置き換える
+                let destination = match dependency_artifact
+                    .destination
+                    .strip_prefix(&dependency_destination)
+                {
+                    Ok(subpath) if !subpath.as_os_str().is_empty() => {
+                        PathBuf::from(&artifact_destination).join(subpath)
+                    }
+                    _ => PathBuf::from(&artifact_destination),
+                };
+                resolved.push(ResolvedArtifact {
+                    source: dependency_artifact.source,
+                    destination,
+                    build_plan: dependency_artifact.build_plan,
+                    placement_method,
+                });
+            }
+            continue;
+        }
+
+        let mut build_plan = resolve_build_plan(
+            build.as_ref(),
+            config.build_group.as_ref(),
+            root,
+            &context,
+            &env,
+        )?;
+        let sources = match &source {
+            ArtifactSource::Cargo(cargo) => {
+                let cargo_build = crate::cargo::locate(cargo, profile.as_ref(), root)
+                    .with_context(|| format!("artifacts.{} の解決に失敗しました", name))?;
+                let path = cargo_build.output.clone();
+                if build.is_none() {
+                    build_plan.cargo = Some(cargo_build);
+                }
+                vec![ResolvedSource {
+                    path,
+                    subpath: None,
+                }]
+            }
+            _ => resolve_source(&source, root, refresh)?,
+        };
+        let mut build_plan = Some(build_plan);
+        for source in sources {
+            let destination = match &source.subpath {
+                Some(subpath) => PathBuf::from(&artifact_destination).join(subpath),
+                None => PathBuf::from(&artifact_destination),
+            };
+            // 複数のファイルに分かれてもビルドは一度だけ
+            let build_plan = build_plan.take().unwrap_or(ResolvedBuild {
+                commands: Vec::new(),
+                group: None,
+                working_dir: None,
+                cargo: None,
+                env: BTreeMap::new(),
+            });
+            resolved.push(ResolvedArtifact {
+                source: root.join(source.path),
+                destination,
+                build_plan,
+                placement_method,
+            });
+        }
+    }
     Ok(resolved)
 }
 
+/// `project` の source が参照するプロジェクトのディレクトリと設定を読み込みます。
+pub(crate) fn load_dependency(
+    root: &Path,
+    name: &str,
+    dependency: &ProjectSource,
+) -> Result<(PathBuf, Config)> {
+    let dependency_root =
+        fs_err::canonicalize(root.join(&dependency.project)).with_context(|| {
+            format!(
+                "artifacts.{} が参照するプロジェクトが見つかりません: {}",
+                name, dependency.project
+            )
+        })?;
+    let dependency_config = config_file_in(&dependency_root)
+        .with_context(|| {
+            format!(
+                "artifacts.{} が参照するプロジェクトに aviutl2.toml がありません: {}",
+                name,
+                dependency_root.display()
+            )
+        })
+        .and_then(|path| read_config(&path))
+        .with_context(|| {
+            format!(
+                "artifacts.{} が参照するプロジェクトの読み込みに失敗しました: {}",
+                name,
+                dependency_root.display()
+            )
+        })?;
+    if !dependency_config
+        .artifacts
+        .contains_key(&dependency.artifact)
+    {
+        bail!(
+            "{} に artifacts.{} がありません",
+            dependency_root.display(),
+            dependency.artifact
+        );
+    }
+    Ok((dependency_root, dependency_config))
+}
+
 pub fn run_build_plan(plan: &ResolvedBuild, executed_groups: &mut HashSet<String>) -> Result<()> {
+    if let Some(cargo) = &plan.cargo {
+        return crate::cargo::build(cargo, plan.working_dir.as_deref(), &plan.env);
+    }
     if let Some(group) = &plan.group {
         if executed_groups.contains(group) {
             return Ok(());
         }
-        run_build_commands(&plan.commands)?;
+        run_build_commands(&plan.commands, plan.working_dir.as_deref(), &plan.env)?;
         executed_groups.insert(group.clone());
         return Ok(());
     }
-    run_build_commands(&plan.commands)
+    run_build_commands(&plan.commands, plan.working_dir.as_deref(), &plan.env)
 }
 
-pub fn run_build_commands(commands: &[String]) -> Result<()> {
+pub fn run_build_commands(
+    commands: &[String],
+    working_dir: Option<&Path>,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
     for cmd in commands {
-        log::info!("コマンド実行: {}", cmd);
-        let status = run_shell_command(cmd)?;
+        match working_dir {
+            Some(dir) => log::info!("コマンド実行: {} ({})", cmd, dir.display()),
+            None => log::info!("コマンド実行: {}", cmd),
+        }
+        let status = run_shell_command(cmd, working_dir, env)?;
         if !status.success() {
             bail!("ビルドコマンドが失敗しました: {}", cmd);
         }
@@ -165,33 +386,54 @@ pub fn run_build_commands(commands: &[String]) -> Result<()> {
 
 pub(crate) fn run_optional_commands(
     commands: Option<&BuildCommand>,
-    build_groups: Option<&std::collections::HashMap<String, BuildCommand>>,
+    config: &Config,
+    profile: &ResolvedProfile,
 ) -> Result<()> {
-    let commands = resolve_build_commands(commands, build_groups)?;
+    let context = TemplateContext::project(&config.project).with_profile(Some(&profile.name));
+    let commands = resolve_build_commands(commands, config.build_group.as_ref(), &context)?;
     if !commands.is_empty() {
-        run_build_commands(&commands)?;
+        run_build_commands(&commands, None, &profile.env)?;
     }
     Ok(())
 }
 
-fn resolve_build_commands(
+/// ビルドコマンドを解決し、`{name}` などを展開します。
+pub(crate) fn resolve_build_commands(
     command: Option<&BuildCommand>,
     build_groups: Option<&std::collections::HashMap<String, BuildCommand>>,
+    context: &TemplateContext,
 ) -> Result<Vec<String>> {
     let mut visiting = std::collections::HashSet::new();
-    resolve_build_commands_inner(command, build_groups, &mut visiting)
+    resolve_build_commands_inner(command, build_groups, &mut visiting)?
+        .iter()
+        .map(|command| expand(command, context))
+        .collect()
 }
 
 fn resolve_build_plan(
     command: Option<&BuildCommand>,
     build_groups: Option<&std::collections::HashMap<String, BuildCommand>>,
+    root: &Path,
+    context: &TemplateContext,
+    env: &BTreeMap<String, String>,
 ) -> Result<ResolvedBuild> {
-    let commands = resolve_build_commands(command, build_groups)?;
+    let commands = resolve_build_commands(command, build_groups, context)?;
+    let working_dir = (!root.as_os_str().is_empty()).then(|| root.to_path_buf());
+    // 同名のグループでもプロジェクトが違えば別々に実行する
     let group = match command {
-        Some(BuildCommand::Group(group_ref)) => Some(group_ref.group.clone()),
+        Some(BuildCommand::Group(group_ref)) => Some(match &working_dir {
+            Some(dir) => format!("{}#{}", dir.display(), group_ref.group),
+            None => group_ref.group.clone(),
+        }),
         _ => None,
     };
-    Ok(ResolvedBuild { commands, group })
+    Ok(ResolvedBuild {
+        commands,
+        group,
+        working_dir,
+        cargo: None,
+        env: env.clone(),
+    })
 }
 
 fn resolve_build_commands_inner(
@@ -219,16 +461,23 @@ fn resolve_build_commands_inner(
     }
 }
 
-fn run_shell_command(command: &str) -> Result<std::process::ExitStatus> {
-    if cfg!(windows) {
-        Command::new("cmd")
-            .args(["/C", command])
-            .status()
-            .map_err(Into::into)
+fn run_shell_command(
+    command: &str,
+    working_dir: Option<&Path>,
+    env: &BTreeMap<String, String>,
+) -> Result<std::process::ExitStatus> {
+    let mut process = if cfg!(windows) {
+        let mut process = Command::new("cmd");
+        process.args(["/C", command]);
+        process
     } else {
-        Command::new("sh")
-            .args(["-c", command])
-            .status()
-            .map_err(Into::into)
+        let mut process = Command::new("sh");
+        process.args(["-c", command]);
+        process
+    };
+    if let Some(dir) = working_dir {
+        process.current_dir(dir);
     }
+    process.envs(env);
+    process.status().map_err(Into::into)
 }
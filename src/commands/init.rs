@@ -4,6 +4,8 @@ use std::path::PathBuf;
 
 const INIT_TEMPLATE: &str = r#"#:schema ./.aviutl2-cli/aviutl2.schema.json
 # 設定ファイルについては https://github.com/sevenc-nanashi/aviutl2-cli を参照してください。
+schema_version = 1
+
 [project]
 name = "{{project_name}}"
 version = "0.1.0"
@@ -48,11 +50,14 @@ pub fn run() -> Result<()> {
     let gitignore_path = PathBuf::from(".gitignore");
     if gitignore_path.exists() {
         let mut content = fs::read_to_string(&gitignore_path)?;
-        content.push_str("\n# AviUtl2 CLI\n/.aviutl2-cli\n/release\n");
+        content.push_str("\n# AviUtl2 CLI\n/.aviutl2-cli\n/release\n/aviutl2.local.toml\n/.config/aviutl2.local.toml\n");
         fs::write(&gitignore_path, content)?;
         log::info!(".gitignore を更新しました");
     } else {
-        fs::write(&gitignore_path, "# AviUtl2 CLI\n/.aviutl2-cli\n/release\n")?;
+        fs::write(
+            &gitignore_path,
+            "# AviUtl2 CLI\n/.aviutl2-cli\n/release\n/aviutl2.local.toml\n/.config/aviutl2.local.toml\n",
+        )?;
         log::info!(".gitignore を作成しました");
     }
     Ok(())
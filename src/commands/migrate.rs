@@ -0,0 +1,156 @@
+use anyhow::{Context, Result, bail};
+use fs_err as fs;
+use std::collections::BTreeSet;
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use crate::config::{BUILTIN_PROFILES, SCHEMA_VERSION, find_config_path, read_layered_config};
+
+/// `schema_version` が n の設定ファイルを n + 1 の形式に書き換える処理。
+/// 2 つ目の引数は、ローカルの設定ファイルを重ねた後の内容です。
+type Migration = fn(&mut DocumentMut, &toml::Table) -> Result<()>;
+
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [declare_profiles];
+
+/// 設定ファイルを現在の形式に書き換えます。コメントや書式は残し、書き込む前に差分を表示します。
+pub fn run(dry_run: bool) -> Result<()> {
+    let path = find_config_path()?;
+    let original = fs::read_to_string(&path)?;
+    let mut document = original
+        .parse::<DocumentMut>()
+        .with_context(|| format!("設定ファイルの解析に失敗しました: {}", path.display()))?;
+    let version = match document.get("schema_version") {
+        Some(item) => item
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .context("schema_version は 0 以上の整数である必要があります")?,
+        None => 0,
+    };
+    if version > SCHEMA_VERSION {
+        bail!(
+            "{} は新しい形式（schema_version = {}）で書かれています。au2 を更新してください",
+            path.display(),
+            version
+        );
+    }
+    if version == SCHEMA_VERSION {
+        log::info!("{} は最新の形式です", path.display());
+        return Ok(());
+    }
+
+    let layered = read_layered_config(&path)?;
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::debug!("schema_version {} から {} に移行します", from, from + 1);
+        migration(&mut document, &layered.table)?;
+    }
+    set_schema_version(&mut document);
+
+    let migrated = document.to_string();
+    let name = path.display().to_string();
+    print!(
+        "{}",
+        similar::TextDiff::from_lines(&original, &migrated)
+            .unified_diff()
+            .header(&name, &name)
+    );
+    if dry_run {
+        log::info!("--dry-run が指定されたので書き込みません");
+        return Ok(());
+    }
+    fs::write(&path, migrated)?;
+    log::info!(
+        "{} を schema_version = {} に更新しました",
+        path.display(),
+        SCHEMA_VERSION
+    );
+    Ok(())
+}
+
+fn set_schema_version(document: &mut DocumentMut) {
+    let version = Value::from(i64::from(SCHEMA_VERSION));
+    if let Some(value) = document
+        .get_mut("schema_version")
+        .and_then(Item::as_value_mut)
+    {
+        let decor = value.decor().clone();
+        *value = version;
+        *value.decor_mut() = decor;
+        return;
+    }
+    // 先頭のコメント（`#:schema` など）は最初のテーブルの前に付いているので、schema_version の前に移す
+    let root = document.as_table_mut();
+    let has_values = root.iter().any(|(_, item)| item.is_value());
+    let first = root
+        .iter_mut()
+        .filter_map(|(_, item)| item.as_table_mut())
+        .filter(|table| table.position().is_some())
+        .min_by_key(|table| table.position());
+    let prefix = match first {
+        Some(table) if !has_values => {
+            let prefix = table
+                .decor()
+                .prefix()
+                .and_then(|prefix| prefix.as_str())
+                .map(str::to_string);
+            if prefix.is_some() {
+                table.decor_mut().set_prefix("\n");
+            }
+            prefix
+        }
+        _ => None,
+    };
+    root.insert("schema_version", Item::Value(version));
+    if let Some(prefix) = prefix
+        && let Some(mut key) = root.key_mut("schema_version")
+    {
+        key.leaf_decor_mut().set_prefix(prefix);
+    }
+}
+
+/// 0 -> 1: 組み込み以外のプロファイルは `[profiles.<name>]` での定義が必要になったので、
+/// 使われているのに定義されていないプロファイルを追加します。
+fn declare_profiles(document: &mut DocumentMut, table: &toml::Table) -> Result<()> {
+    let mut used = BTreeSet::new();
+    for artifact in table
+        .get("artifacts")
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(toml::Table::values)
+    {
+        if let Some(profiles) = artifact.get("profiles").and_then(toml::Value::as_table) {
+            used.extend(profiles.keys().cloned());
+        }
+    }
+    for section in ["development", "preview", "release"] {
+        if let Some(profile) = table
+            .get(section)
+            .and_then(|section| section.get("profile"))
+            .and_then(toml::Value::as_str)
+        {
+            used.insert(profile.to_string());
+        }
+    }
+    let declared = table.get("profiles").and_then(toml::Value::as_table);
+    let missing = used
+        .into_iter()
+        .filter(|name| !BUILTIN_PROFILES.contains(&name.as_str()))
+        .filter(|name| !declared.is_some_and(|declared| declared.contains_key(name)))
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let profiles = document
+        .entry("profiles")
+        .or_insert_with(|| {
+            let mut profiles = Table::new();
+            profiles.set_implicit(true);
+            Item::Table(profiles)
+        })
+        .as_table_mut()
+        .context("profiles がテーブルではありません")?;
+    for name in missing {
+        log::info!("プロファイル {} の定義を追加します", name);
+        profiles.insert(&name, Item::Table(Table::new()));
+    }
+    Ok(())
+}
@@ -1,5 +1,10 @@
+mod aviutl2;
+mod cache;
+mod check;
+mod config;
 mod develop;
 mod init;
+mod migrate;
 mod prepare;
 mod preview;
 mod release;
@@ -7,17 +12,45 @@ mod schema;
 
 use anyhow::Result;
 
-use crate::cli::Commands;
+use crate::cli::{Aviutl2Commands, CacheCommands, Cli, Commands, ConfigCommands};
+use crate::config::enter_project;
 
-pub fn run(command: Commands) -> Result<()> {
-    match command {
+pub fn run(cli: Cli) -> Result<()> {
+    // init はカレントディレクトリに新しいプロジェクトを作るので、親のプロジェクトには移動しない
+    if !matches!(cli.command, Commands::Init) {
+        enter_project(cli.config.as_deref())?;
+    }
+    match cli.command {
         Commands::Init => init::run(),
-        Commands::Prepare { force, refresh } => {
+        Commands::Prepare {
+            force,
+            profile,
+            refresh,
+        } => {
             schema::run()?;
-            prepare::aviutl2()?;
-            prepare::artifacts(force, None, refresh)
+            prepare::aviutl2(false, None)?;
+            prepare::artifacts(force, profile, refresh)
+        }
+        Commands::Check => check::run(),
+        Commands::Config(ConfigCommands::Show {
+            origin,
+            profile,
+            command,
+            json,
+        }) => {
+            if origin {
+                config::show_origin()
+            } else {
+                config::show_resolved(profile, command, json)
+            }
         }
-        Commands::PrepareAviUtl2 => prepare::aviutl2(),
+        Commands::Migrate { dry_run } => migrate::run(dry_run),
+        Commands::PrepareAviUtl2 { profile } => prepare::aviutl2(true, profile),
+        Commands::Aviutl2(Aviutl2Commands::List { json }) => aviutl2::list(json),
+        Commands::Aviutl2(Aviutl2Commands::Installed { json }) => aviutl2::installed(json),
+        Commands::Cache(CacheCommands::List { json }) => cache::list(json),
+        Commands::Cache(CacheCommands::Prune { older_than }) => cache::prune(&older_than),
+        Commands::Cache(CacheCommands::Clean) => cache::clean(),
         Commands::PrepareArtifacts {
             force,
             profile,
@@ -3,58 +3,341 @@ use fs_err as fs;
 use fs_err::File;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use xxhash_rust::xxh3::Xxh3;
 
-use crate::config::{Artifact, PlacementMethod, load_config};
+use crate::config::{
+    ApiBase, Artifact, Aviutl2Version, Config, Development, PlacementMethod, load_config,
+};
+use crate::download::{DownloadRequest, download_file};
+use crate::template::{TemplateContext, expand_all};
 use crate::util::{
-    copy_to_destination, create_symlink, development_dir, extract_zip, find_aviutl2_data_dir,
-    prepare_snapshot_path,
+    Checksum, aviutl2_cache_dir, copy_dir_contents, copy_to_destination, create_symlink,
+    development_dir, extract_zip, find_aviutl2_data_dir, prepare_snapshot_path, remove_path,
+    safe_join, verify_checksum,
 };
 
-const API_BASE: &str = "https://api.aviutl2.jp";
+const DEFAULT_API_BASE: &str = "https://api.aviutl2.jp";
+const API_BASE_ENV: &str = "AU2_API_BASE";
+const DEFAULT_PRESERVE: &[&str] = &["aviutl2.conf"];
 
-pub fn aviutl2() -> Result<()> {
+/// `place_after_upgrade` の場合、アップグレードしたら `profile`（なければ前回配置したときのもの）で成果物を再配置します。
+pub fn aviutl2(place_after_upgrade: bool, profile: Option<String>) -> Result<()> {
     let config = load_config()?;
     let dev = config
         .development
         .as_ref()
         .context("development 設定が必要です")?;
-    let install_dir = development_dir(dev)?;
-    aviutl2_in(&install_dir, &dev.aviutl2_version)
+    let install_dir = development_dir(Some(dev))?;
+    let upgraded = aviutl2_in(
+        &install_dir,
+        &dev.aviutl2_version,
+        &api_bases(Some(dev)),
+        &preserve_list(dev.preserve.as_deref()),
+    )?;
+    if upgraded
+        && place_after_upgrade
+        && let Some(snapshot) = load_prepare_snapshot()?
+    {
+        log::info!("アップグレード後の AviUtl2 に成果物を再配置します");
+        place_artifacts(&config, false, profile.or(snapshot.profile), false)?;
+    }
+    Ok(())
 }
 
-pub fn aviutl2_in(install_dir: &PathBuf, aviutl2_version: &str) -> Result<()> {
-    fs::create_dir_all(install_dir)
-        .with_context(|| format!("ディレクトリ作成に失敗しました: {}", install_dir.display()))?;
-    if let Ok(current_version) = fs::read_to_string(install_dir.join(".aviutl2-version"))
-        && current_version == aviutl2_version
-    {
+/// アップグレード時に引き継ぐパス（data ディレクトリからの相対パス）を返します。
+pub fn preserve_list(preserve: Option<&[String]>) -> Vec<String> {
+    match preserve {
+        Some(preserve) => preserve.to_vec(),
+        None => DEFAULT_PRESERVE.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+/// AviUtl2 の API のベース URL を優先順に返します。
+/// `AU2_API_BASE`（カンマ区切り）、`development.api_base`、デフォルトの順に参照します。
+pub fn api_bases(dev: Option<&Development>) -> Vec<String> {
+    let from_env = std::env::var(API_BASE_ENV)
+        .ok()
+        .map(|value| {
+            value
+                .split(',')
+                .map(|base| base.trim().to_string())
+                .filter(|base| !base.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|bases| !bases.is_empty());
+    let bases = from_env.unwrap_or_else(|| match dev.and_then(|dev| dev.api_base.clone()) {
+        Some(ApiBase::Single(base)) => vec![base],
+        Some(ApiBase::Multiple(bases)) => bases,
+        None => Vec::new(),
+    });
+    if bases.is_empty() {
+        return vec![DEFAULT_API_BASE.to_string()];
+    }
+    bases
+        .into_iter()
+        .map(|base| base.trim_end_matches('/').to_string())
+        .collect()
+}
+
+/// AviUtl2 を `install_dir` に用意します。
+/// 既存のインストールを別のバージョンに置き換えた場合は `true` を返します。
+pub fn aviutl2_in(
+    install_dir: &PathBuf,
+    aviutl2_version: &Aviutl2Version,
+    api_bases: &[String],
+    preserve: &[String],
+) -> Result<bool> {
+    let aviutl2_version = &expand_all(aviutl2_version, &TemplateContext::default())
+        .context("aviutl2_version の展開に失敗しました")?;
+    let fingerprint = aviutl2_fingerprint(aviutl2_version)?;
+    let version_path = install_dir.join(".aviutl2-version");
+    let current_version = fs::read_to_string(&version_path).ok();
+    if current_version.as_deref() == Some(fingerprint.as_str()) {
         log::info!("AviUtl2 のバージョンが一致しています: {}", aviutl2_version);
-        return Ok(());
+        return Ok(false);
+    }
+
+    if current_version.is_none() {
+        fs::create_dir_all(install_dir).with_context(|| {
+            format!("ディレクトリ作成に失敗しました: {}", install_dir.display())
+        })?;
+        install_aviutl2(install_dir, aviutl2_version, api_bases)?;
+        fs::write(&version_path, &fingerprint)?;
+        record_release(install_dir, aviutl2_version, api_bases)?;
+        return Ok(false);
+    }
+
+    // 上書き展開すると上流で削除されたファイルが残るため、新しいディレクトリに展開してから入れ替える
+    let staging_dir = sibling_dir(install_dir, "aviutl2-new")?;
+    remove_path(&staging_dir)?;
+    fs::create_dir_all(&staging_dir)
+        .with_context(|| format!("ディレクトリ作成に失敗しました: {}", staging_dir.display()))?;
+    if let Err(err) = install_aviutl2(&staging_dir, aviutl2_version, api_bases) {
+        remove_path(&staging_dir).ok();
+        return Err(err);
+    }
+    if let Err(err) = migrate_user_data(install_dir, &staging_dir, preserve) {
+        remove_path(&staging_dir).ok();
+        return Err(err);
+    }
+    fs::write(staging_dir.join(".aviutl2-version"), &fingerprint)?;
+    record_release(&staging_dir, aviutl2_version, api_bases)?;
+
+    let old_dir = sibling_dir(install_dir, "aviutl2-old")?;
+    remove_path(&old_dir)?;
+    fs::rename(install_dir, &old_dir).with_context(|| {
+        format!(
+            "既存の AviUtl2 を退避できませんでした（AviUtl2 が起動していないか確認してください）: {}",
+            install_dir.display()
+        )
+    })?;
+    if let Err(err) = fs::rename(&staging_dir, install_dir) {
+        fs::rename(&old_dir, install_dir).ok();
+        return Err(err.into());
+    }
+    remove_path(&old_dir)?;
+    log::info!(
+        "AviUtl2 をアップグレードしました: {} -> {}",
+        current_version.unwrap_or_default(),
+        aviutl2_version
+    );
+    Ok(true)
+}
+
+fn install_aviutl2(
+    install_dir: &Path,
+    aviutl2_version: &Aviutl2Version,
+    api_bases: &[String],
+) -> Result<()> {
+    match aviutl2_version {
+        Aviutl2Version::Release(version) => {
+            let zip_path = download_aviutl2_zip(version, &Checksum::default(), api_bases)?;
+            extract_zip(&zip_path, install_dir)?;
+            log::info!("AviUtl2 を展開しました: {}", install_dir.display());
+        }
+        Aviutl2Version::Pinned(release) => {
+            let checksum = Checksum {
+                sha256: release.sha256.as_deref(),
+                xxh3: release.xxh3.as_deref(),
+            };
+            let zip_path = download_aviutl2_zip(&release.version, &checksum, api_bases)?;
+            extract_zip(&zip_path, install_dir)?;
+            log::info!("AviUtl2 を展開しました: {}", install_dir.display());
+        }
+        Aviutl2Version::Archive(archive) => {
+            let checksum = Checksum {
+                sha256: archive.sha256.as_deref(),
+                xxh3: archive.xxh3.as_deref(),
+            };
+            verify_checksum(Path::new(&archive.path), &checksum)
+                .with_context(|| "AviUtl2 の zip の検証に失敗しました")?;
+            extract_zip(Path::new(&archive.path), install_dir)?;
+            log::info!(
+                "AviUtl2 を展開しました: {} -> {}",
+                archive.path,
+                install_dir.display()
+            );
+        }
+        Aviutl2Version::Directory(directory) => {
+            copy_dir_contents(Path::new(&directory.dir), install_dir, true)?;
+            log::info!(
+                "AviUtl2 をコピーしました: {} -> {}",
+                directory.dir,
+                install_dir.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// インストールしたリリースのバージョンを `au2 aviutl2 list` で照合できるように記録します。
+/// ローカルの zip やディレクトリはバージョンが分からないので記録しません。
+fn record_release(
+    install_dir: &Path,
+    aviutl2_version: &Aviutl2Version,
+    api_bases: &[String],
+) -> Result<()> {
+    let path = install_dir.join(super::aviutl2::RELEASE_FILE);
+    let version = match aviutl2_version {
+        Aviutl2Version::Release(version) => Some(version.as_str()),
+        Aviutl2Version::Pinned(release) => Some(release.version.as_str()),
+        Aviutl2Version::Archive(_) | Aviutl2Version::Directory(_) => None,
+    };
+    let version = match version {
+        Some("latest") => match super::aviutl2::latest_release(api_bases) {
+            Ok(version) => Some(version),
+            Err(err) => {
+                log::debug!("latest のバージョンを取得できませんでした: {:#}", err);
+                None
+            }
+        },
+        version => version.map(str::to_string),
+    };
+    match version {
+        Some(version) => fs::write(&path, version)?,
+        None => remove_path(&path)?,
     }
+    Ok(())
+}
 
-    let zip_path = download_aviutl2_zip(aviutl2_version)?;
-    extract_zip(&zip_path, install_dir)?;
-    fs::remove_file(&zip_path).ok();
-    log::info!("AviUtl2 を展開しました: {}", install_dir.display());
-    let mut version = File::create(install_dir.join(".aviutl2-version"))?;
-    version.write_all(aviutl2_version.as_bytes())?;
+/// 旧インストールの data ディレクトリから `preserve` に含まれるパスを新しいインストールにコピーします。
+/// 入れ替えに失敗しても旧インストール側に残るように、移動はしません。
+fn migrate_user_data(old_dir: &Path, new_dir: &Path, preserve: &[String]) -> Result<()> {
+    if preserve.is_empty() {
+        return Ok(());
+    }
+    let old_data_dir = match find_aviutl2_data_dir(old_dir) {
+        Ok(dir) => dir,
+        Err(err) => {
+            log::warn!("旧バージョンのデータを引き継げませんでした: {:#}", err);
+            return Ok(());
+        }
+    };
+    let new_data_dir = find_aviutl2_data_dir(new_dir)?;
+    for entry in preserve {
+        let from = safe_join(&old_data_dir, entry)
+            .with_context(|| format!("preserve のパスが不正です: {entry}"))?;
+        if fs::symlink_metadata(&from).is_err() {
+            continue;
+        }
+        let to = safe_join(&new_data_dir, entry)
+            .with_context(|| format!("preserve のパスが不正です: {entry}"))?;
+        remove_path(&to)?;
+        if from.is_dir() {
+            copy_dir_contents(&from, &to, true)?;
+        } else {
+            copy_to_destination(&from, &to, true)?;
+        }
+        log::info!("ユーザーデータを引き継ぎました: {}", entry);
+    }
     Ok(())
 }
 
+fn sibling_dir(dir: &Path, suffix: &str) -> Result<PathBuf> {
+    let name = dir
+        .file_name()
+        .with_context(|| format!("インストール先のパスが不正です: {}", dir.display()))?;
+    Ok(dir.with_file_name(format!("{}.{}", name.to_string_lossy(), suffix)))
+}
+
+/// `.aviutl2-version` に記録する値を返します。
+/// ローカルの zip やディレクトリは内容のハッシュを含めるため、中身が変わると再展開されます。
+fn aviutl2_fingerprint(aviutl2_version: &Aviutl2Version) -> Result<String> {
+    match aviutl2_version {
+        Aviutl2Version::Release(version) => Ok(version.clone()),
+        Aviutl2Version::Pinned(release) => Ok(release.version.clone()),
+        Aviutl2Version::Archive(archive) => {
+            let path = Path::new(&archive.path);
+            let mut file = File::open(path)
+                .with_context(|| format!("AviUtl2 の zip が見つかりません: {}", path.display()))?;
+            let mut hasher = Xxh3::new();
+            std::io::copy(&mut file, &mut HashWriter(&mut hasher))?;
+            Ok(format!("path:{}:{:032x}", archive.path, hasher.digest128()))
+        }
+        Aviutl2Version::Directory(directory) => {
+            let dir = Path::new(&directory.dir);
+            if !dir.is_dir() {
+                bail!("AviUtl2 のディレクトリが見つかりません: {}", dir.display());
+            }
+            let mut hasher = Xxh3::new();
+            for entry in WalkDir::new(dir)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+            {
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let metadata = entry.metadata()?;
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                let rel = entry.path().strip_prefix(dir)?;
+                hasher.update(rel.to_string_lossy().as_bytes());
+                hasher.update(&metadata.len().to_le_bytes());
+                hasher.update(&modified.as_nanos().to_le_bytes());
+            }
+            Ok(format!("dir:{}:{:032x}", directory.dir, hasher.digest128()))
+        }
+    }
+}
+
+struct HashWriter<'a>(&'a mut Xxh3);
+
+impl Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 pub fn artifacts(force: bool, profile: Option<String>, refresh: bool) -> Result<()> {
     let config = load_config()?;
+    place_artifacts(&config, force, profile, refresh)
+}
+
+fn place_artifacts(
+    config: &Config,
+    force: bool,
+    profile: Option<String>,
+    refresh: bool,
+) -> Result<()> {
     let dev = config
         .development
         .as_ref()
         .context("development 設定が必要です")?;
-    let install_dir = development_dir(dev)?;
-    let profile = profile
-        .as_deref()
-        .or(dev.profile.as_deref())
-        .unwrap_or("debug");
-    let artifacts = super::develop::resolve_artifacts(&config, Some(profile), None, refresh)?;
+    let install_dir = development_dir(Some(dev))?;
+    let profile = super::develop::profile_name(config, profile);
+    let artifacts = super::develop::resolve_artifacts(config, Some(&profile), None, refresh)?;
     let data_dir = find_aviutl2_data_dir(&install_dir)?;
 
     for artifact in artifacts {
@@ -91,27 +374,44 @@ pub fn artifacts(force: bool, profile: Option<String>, refresh: bool) -> Result<
         }
     }
     log::info!("成果物のシンボリックリンクを作成しました");
-    save_prepare_snapshot(&config.artifacts, &dev.aviutl2_version)?;
+    save_prepare_snapshot(&config.artifacts, &dev.aviutl2_version, &profile)?;
     Ok(())
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct PrepareSnapshot {
-    pub aviutl2_version: String,
+    pub aviutl2_version: Aviutl2Version,
     pub artifacts: BTreeMap<String, Artifact>,
+    /// 成果物を配置したときのプロファイル
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+impl PrepareSnapshot {
+    /// ヘッダーの値は伏せて記録します。
+    pub fn new(
+        artifacts: &std::collections::HashMap<String, Artifact>,
+        aviutl2_version: &Aviutl2Version,
+    ) -> Self {
+        Self {
+            aviutl2_version: aviutl2_version.clone(),
+            artifacts: artifacts
+                .iter()
+                .map(|(name, artifact)| (name.clone(), artifact.redacted()))
+                .collect(),
+            profile: None,
+        }
+    }
 }
 
 pub fn save_prepare_snapshot(
     artifacts: &std::collections::HashMap<String, Artifact>,
-    aviutl2_version: &str,
+    aviutl2_version: &Aviutl2Version,
+    profile: &str,
 ) -> Result<()> {
-    let mut ordered = BTreeMap::new();
-    for (name, artifact) in artifacts {
-        ordered.insert(name.clone(), artifact.clone());
-    }
     let snapshot = PrepareSnapshot {
-        aviutl2_version: aviutl2_version.to_string(),
-        artifacts: ordered,
+        profile: Some(profile.to_string()),
+        ..PrepareSnapshot::new(artifacts, aviutl2_version)
     };
     let snapshot_path = prepare_snapshot_path()?;
     if let Some(parent) = snapshot_path.parent() {
@@ -132,34 +432,76 @@ pub fn load_prepare_snapshot() -> Result<Option<PrepareSnapshot>> {
     Ok(Some(snapshot))
 }
 
-fn download_aviutl2_zip(version: &str) -> Result<PathBuf> {
-    let mut url = String::from(API_BASE);
-    url.push_str("/download");
-    let agent: ureq::Agent = ureq::Agent::config_builder()
-        .max_redirects(5)
-        .build()
-        .into();
-    let response = agent
-        .get(&url)
-        .query("version", version)
-        .query("type", "zip")
-        .header("User-Agent", "aviutl2-cli")
-        .call()
-        .with_context(|| "AviUtl2 のダウンロードに失敗しました")?;
-    let status = response.status();
-    if !status.is_success() {
-        bail!("AviUtl2 のダウンロードに失敗しました: {}", status);
-    }
-
-    let (_parts, body) = response.into_parts();
-    let mut reader = body.into_reader();
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf)?;
-
-    let file_name = format!("aviutl2-{}.zip", version.replace('/', "_"));
-    let mut tmp_path = std::env::temp_dir();
-    tmp_path.push(file_name);
-    let mut file = File::create(&tmp_path)?;
-    file.write_all(&buf)?;
-    Ok(tmp_path)
+/// AviUtl2 の zip をキャッシュから取り出すか、ダウンロードしてキャッシュに保存します。
+/// `latest` は指す内容が変わるため、毎回ダウンロードし直します。
+fn download_aviutl2_zip(
+    version: &str,
+    checksum: &Checksum,
+    api_bases: &[String],
+) -> Result<PathBuf> {
+    let zip_path = aviutl2_cache_path(version)?;
+    if version != "latest" && zip_path.exists() {
+        match verify_checksum(&zip_path, checksum) {
+            Ok(()) => {
+                log::info!("AviUtl2 のキャッシュを使用します: {}", zip_path.display());
+                // `au2 cache prune` で最後に使った日時として扱う
+                fs::OpenOptions::new()
+                    .append(true)
+                    .open(&zip_path)?
+                    .file()
+                    .set_modified(std::time::SystemTime::now())?;
+                return Ok(zip_path);
+            }
+            Err(err) => {
+                log::warn!("キャッシュを破棄して再取得します: {:#}", err);
+            }
+        }
+    }
+
+    let mut last_error = None;
+    for (i, api_base) in api_bases.iter().enumerate() {
+        match download_aviutl2_zip_from(api_base, version, checksum, &zip_path) {
+            Ok(()) => {
+                last_error = None;
+                break;
+            }
+            Err(err) => {
+                if i + 1 < api_bases.len() {
+                    log::warn!(
+                        "AviUtl2 のダウンロードに失敗したため次のミラーを試します: {}: {:#}",
+                        api_base,
+                        err
+                    );
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+    if let Some(err) = last_error {
+        return Err(err);
+    }
+    Ok(zip_path)
+}
+
+/// キャッシュ内の AviUtl2 の zip のパス。
+pub fn aviutl2_cache_path(version: &str) -> Result<PathBuf> {
+    Ok(aviutl2_cache_dir()?.join(format!("aviutl2-{}.zip", version.replace('/', "_"))))
+}
+
+/// 検証に失敗した場合は以前のキャッシュを残します。
+fn download_aviutl2_zip_from(
+    api_base: &str,
+    version: &str,
+    checksum: &Checksum,
+    zip_path: &Path,
+) -> Result<()> {
+    let url = format!("{api_base}/download");
+    let request = DownloadRequest {
+        query: &[("version", version), ("type", "zip")],
+        checksum: *checksum,
+        ..DownloadRequest::new(&url)
+    };
+    download_file(&request, zip_path)
+        .with_context(|| format!("AviUtl2 のダウンロードに失敗しました: {url}"))?;
+    Ok(())
 }
@@ -1,7 +1,8 @@
 use anyhow::{Context, Result};
 use std::process::Command;
 
-use crate::config::load_config;
+use crate::config::{Config, load_config};
+use crate::template::TemplateContext;
 use crate::util::{copy_dir_contents, find_aviutl2_data_dir, preview_dir};
 
 pub fn run(
@@ -19,26 +20,29 @@ pub fn run(
         .context("preview.aviutl2_version を省略する場合は development 設定が必要です")?;
     let aviutl2_version = preview
         .aviutl2_version
-        .as_deref()
+        .as_ref()
         .unwrap_or(&dev.aviutl2_version);
-    let install_dir = preview_dir(preview)?;
-    super::prepare::aviutl2_in(&install_dir, aviutl2_version)?;
+    let install_dir = preview_dir(Some(preview))?;
+    super::prepare::aviutl2_in(
+        &install_dir,
+        aviutl2_version,
+        &super::prepare::api_bases(Some(dev)),
+        &super::prepare::preserve_list(preview.preserve.as_deref()),
+    )?;
 
-    let profile = profile
-        .or_else(|| preview.profile.clone())
-        .or_else(|| release.profile.clone())
-        .unwrap_or_else(|| "release".to_string());
+    let profile = config.profile(&profile_name(&config, profile))?;
     let include = preview.include.as_deref().or(release.include.as_deref());
-    super::develop::run_optional_commands(preview.prebuild.as_ref(), config.build_group.as_ref())?;
+    let context = TemplateContext::project(&config.project).with_profile(Some(&profile.name));
+    super::develop::run_optional_commands(preview.prebuild.as_ref(), &config, &profile)?;
     let mut artifacts =
-        super::develop::resolve_artifacts(&config, Some(&profile), include, refresh)?;
+        super::develop::resolve_artifacts(&config, Some(&profile.name), include, refresh)?;
     artifacts.retain(|artifact| &artifact.destination != "preview.txt");
     let stage_dir =
-        super::release::build_release_stage_from_artifacts(artifacts, None, &config.project)?;
+        super::release::build_release_stage_from_artifacts(artifacts, None, &context, false)?;
     let data_dir = find_aviutl2_data_dir(&install_dir)?;
     copy_dir_contents(&stage_dir, &data_dir, true)?;
     log::info!("プレビュー用に成果物を配置しました");
-    super::develop::run_optional_commands(preview.postbuild.as_ref(), config.build_group.as_ref())?;
+    super::develop::run_optional_commands(preview.postbuild.as_ref(), &config, &profile)?;
 
     if !skip_start {
         let aviutl_exe = data_dir.parent().unwrap_or(&data_dir).join("aviutl2.exe");
@@ -54,3 +58,11 @@ pub fn run(
     }
     Ok(())
 }
+
+/// 指定がなければ preview.profile、release.profile の順に使い、どちらもなければ release を使います。
+pub(crate) fn profile_name(config: &Config, profile: Option<String>) -> String {
+    profile
+        .or_else(|| config.preview.as_ref()?.profile.clone())
+        .or_else(|| config.release.as_ref()?.profile.clone())
+        .unwrap_or_else(|| "release".to_string())
+}
@@ -5,7 +5,9 @@ use std::path::PathBuf;
 use crate::{
     catalog_schema,
     config::{self, Config, load_config},
-    util::{copy_to_destination, create_zip, fill_template, release_stage_dir},
+    pe,
+    template::{TemplateContext, expand, expand_all, expand_env},
+    util::{copy_to_destination, create_zip, release_stage_dir},
 };
 
 pub fn run(profile: Option<String>, set_version: Option<String>) -> Result<()> {
@@ -14,42 +16,33 @@ pub fn run(profile: Option<String>, set_version: Option<String>) -> Result<()> {
         config.project.version = version;
     }
     let release = config.release.as_ref().context("release 設定が必要です")?;
-    let profile = profile
-        .or_else(|| release.profile.clone())
-        .unwrap_or_else(|| "release".to_string());
-    let output_dir = PathBuf::from(release.output_dir.as_deref().unwrap_or("release"));
+    let profile = config.profile(&profile_name(&config, profile))?;
+    let context = TemplateContext::project(&config.project).with_profile(Some(&profile.name));
+    let output_dir = output_dir(release)?;
     fs::create_dir_all(&output_dir)?;
-    super::develop::run_optional_commands(release.prebuild.as_ref(), config.build_group.as_ref())?;
+    super::develop::run_optional_commands(release.prebuild.as_ref(), &config, &profile)?;
     let stage_dir = build_release_stage(
         &config,
-        &profile,
+        &profile.name,
         release.include.as_deref(),
         release.package_template.as_deref(),
         false,
     )?;
 
-    let zip_base = release
-        .zip_name
-        .clone()
-        .unwrap_or_else(|| "{name}-v{version}".to_string());
-    let zip_name = fill_template(&zip_base, &config.project);
-    let zip_file_name = if zip_name.ends_with(".au2pkg.zip") {
-        zip_name
-    } else {
-        format!("{zip_name}.au2pkg.zip")
-    };
-    let zip_path = output_dir.join(zip_file_name);
+    let zip_path = output_dir.join(zip_file_name(release, &context)?);
     create_zip(&stage_dir, &zip_path)?;
     log::info!("リリースパッケージを作成しました: {}", zip_path.display());
-    super::develop::run_optional_commands(release.postbuild.as_ref(), config.build_group.as_ref())?;
+    super::develop::run_optional_commands(release.postbuild.as_ref(), &config, &profile)?;
 
     if let Some(catalog_config) = &config.catalog {
+        let catalog_config = expand_all(catalog_config, &TemplateContext::default())
+            .context("catalog の展開に失敗しました")?;
+        let catalog_config = &catalog_config;
         log::warn!(
             "カタログ生成機能は実験的機能です。将来のバージョンで変更または削除される可能性があります。"
         );
         let versions = build_versions(&config, &stage_dir)?;
-        let generated_pattern =
-            generate_au2pkg_pattern(&config.project, release.zip_name.as_deref());
+        let generated_pattern = generate_au2pkg_pattern(&config.project, release, &profile.name)?;
         let catalog_index =
             build_catalog_index(catalog_config, &stage_dir, &versions, &generated_pattern)?;
         let catalog_json = serde_json::to_string_pretty(&catalog_index)
@@ -66,6 +59,33 @@ pub fn run(profile: Option<String>, set_version: Option<String>) -> Result<()> {
     Ok(())
 }
 
+/// 指定がなければ release.profile、それもなければ release を使います。
+pub(crate) fn profile_name(config: &Config, profile: Option<String>) -> String {
+    profile
+        .or_else(|| config.release.as_ref()?.profile.clone())
+        .unwrap_or_else(|| "release".to_string())
+}
+
+pub(crate) fn output_dir(release: &config::Release) -> Result<PathBuf> {
+    let output_dir = expand_env(release.output_dir.as_deref().unwrap_or("release"))
+        .context("release.output_dir の展開に失敗しました")?;
+    Ok(PathBuf::from(output_dir))
+}
+
+/// 作成するリリースパッケージのファイル名
+pub(crate) fn zip_file_name(
+    release: &config::Release,
+    context: &TemplateContext,
+) -> Result<String> {
+    let zip_base = release.zip_name.as_deref().unwrap_or("{name}-v{version}");
+    let zip_name = expand(zip_base, context)?;
+    Ok(if zip_name.ends_with(".au2pkg.zip") {
+        zip_name
+    } else {
+        format!("{zip_name}.au2pkg.zip")
+    })
+}
+
 pub(crate) fn build_release_stage(
     config: &Config,
     profile: &str,
@@ -74,13 +94,15 @@ pub(crate) fn build_release_stage(
     refresh: bool,
 ) -> Result<PathBuf> {
     let artifacts = super::develop::resolve_artifacts(config, Some(profile), include, refresh)?;
-    build_release_stage_from_artifacts(artifacts, package_template, &config.project)
+    let context = TemplateContext::project(&config.project).with_profile(Some(profile));
+    build_release_stage_from_artifacts(artifacts, package_template, &context, true)
 }
 
 pub(crate) fn build_release_stage_from_artifacts(
     artifacts: Vec<super::develop::ResolvedArtifact>,
     package_template: Option<&str>,
-    project: &crate::config::Project,
+    context: &TemplateContext,
+    strict: bool,
 ) -> Result<PathBuf> {
     let stage_dir = release_stage_dir()?;
     if stage_dir.exists() {
@@ -91,6 +113,13 @@ pub(crate) fn build_release_stage_from_artifacts(
     let mut executed_groups = std::collections::HashSet::new();
     for artifact in artifacts {
         super::develop::run_build_plan(&artifact.build_plan, &mut executed_groups)?;
+        // strict の場合は壊れたプラグインをパッケージに含めない
+        if let Err(err) = pe::verify_plugin(&artifact.source, &artifact.destination) {
+            if strict {
+                return Err(err);
+            }
+            log::warn!("{:#}", err);
+        }
         copy_to_destination(
             &artifact.source,
             &stage_dir.join(&artifact.destination),
@@ -107,7 +136,7 @@ pub(crate) fn build_release_stage_from_artifacts(
                 template_path.display()
             )
         })?;
-        let content = fill_template(&content, project);
+        let content = fill_package_template(&content, context);
         let content = normalize_to_crlf(&content);
         fs::write(&target, content).with_context(|| {
             format!("package.txt の書き込みに失敗しました: {}", target.display())
@@ -116,6 +145,18 @@ pub(crate) fn build_release_stage_from_artifacts(
     Ok(stage_dir)
 }
 
+/// package.txt は自由に書く説明文なので、`{name}` と `{version}` だけを置き換えます。
+fn fill_package_template(template: &str, context: &TemplateContext) -> String {
+    let mut content = template.to_string();
+    if let Some(name) = context.name {
+        content = content.replace("{name}", name);
+    }
+    if let Some(version) = context.version {
+        content = content.replace("{version}", version);
+    }
+    content
+}
+
 fn normalize_to_crlf(input: &str) -> String {
     let normalized = input.replace("\r\n", "\n");
     normalized.replace('\n', "\r\n")
@@ -392,23 +433,33 @@ fn map_source(
     }
 }
 
-fn generate_au2pkg_pattern(project: &crate::config::Project, zip_base: Option<&str>) -> String {
-    let zip_base = zip_base.unwrap_or("{name}-v{version}");
+/// カタログの download_source で使う、リリースパッケージのファイル名の正規表現
+pub(crate) fn generate_au2pkg_pattern(
+    project: &crate::config::Project,
+    release: &config::Release,
+    profile: &str,
+) -> Result<String> {
+    // 環境変数はパッケージのファイル名と同じ値にする
+    let zip_base = expand_env(release.zip_name.as_deref().unwrap_or("{name}-v{version}"))
+        .context("release.zip_name の展開に失敗しました")?;
     let zip_name_template = if zip_base.ends_with(".au2pkg.zip") {
-        zip_base.to_string()
+        zip_base
     } else {
         format!("{zip_base}.au2pkg.zip")
     };
 
     let name_token = "__AU2_NAME_TOKEN__";
     let version_token = "__AU2_VERSION_TOKEN__";
+    let profile_token = "__AU2_PROFILE_TOKEN__";
     let tokenized = zip_name_template
         .replace("{name}", name_token)
-        .replace("{version}", version_token);
+        .replace("{version}", version_token)
+        .replace("{profile}", profile_token);
     let mut escaped = regex_escape(&tokenized);
     escaped = escaped.replace(name_token, &regex_escape(&project.name));
+    escaped = escaped.replace(profile_token, &regex_escape(profile));
     escaped = escaped.replace(version_token, "[^/]+");
-    format!("^{escaped}$")
+    Ok(format!("^{escaped}$"))
 }
 
 fn regex_escape(input: &str) -> String {
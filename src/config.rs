@@ -1,11 +1,16 @@
 use anyhow::{Context, Result, bail};
 use fs_err as fs;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::{schema, template, version};
 
 #[derive(Deserialize)]
 pub struct Config {
+    /// 設定ファイルの形式のバージョン（省略時は 0）
+    pub schema_version: Option<u32>,
     pub project: Project,
     pub artifacts: HashMap<String, Artifact>,
     pub build_group: Option<HashMap<String, BuildCommand>>,
@@ -13,29 +18,338 @@ pub struct Config {
     pub preview: Option<Preview>,
     pub release: Option<Release>,
     pub catalog: Option<Catalog>,
+    pub profiles: Option<HashMap<String, Profile>>,
+}
+
+/// 現在の設定ファイルの形式のバージョン。形式を変えたら `au2 migrate` の移行処理も追加します。
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// 定義しなくても使えるプロファイル
+pub const BUILTIN_PROFILES: [&str; 2] = ["debug", "release"];
+
+/// `[profiles.<name>]` で定義するプロファイル
+#[derive(Deserialize, Clone, Default)]
+pub struct Profile {
+    /// 設定を引き継ぐプロファイル
+    pub inherits: Option<String>,
+    /// ビルドコマンドに渡す環境変数
+    pub env: Option<BTreeMap<String, String>>,
+    /// 含める成果物のリスト（各コマンドの include が優先されます）
+    pub include: Option<Vec<String>>,
+    /// 成果物の有効/無効のデフォルト
+    pub enabled: Option<bool>,
+}
+
+/// 継承元の設定を合わせたプロファイル
+pub struct ResolvedProfile {
+    pub name: String,
+    /// 自身から継承元へ順に並べたプロファイル名
+    pub chain: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub include: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+impl Config {
+    /// プロファイルの定義を返します。`debug` と `release` は定義されていなくても使えます。
+    pub fn profile_definition(&self, name: &str) -> Option<Profile> {
+        match self
+            .profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+        {
+            Some(profile) => Some(profile.clone()),
+            None if BUILTIN_PROFILES.contains(&name) => Some(Profile::default()),
+            None => None,
+        }
+    }
+
+    /// プロファイルを継承元までたどって解決します。定義されていないプロファイルはエラーです。
+    pub fn profile(&self, name: &str) -> Result<ResolvedProfile> {
+        let mut chain: Vec<String> = Vec::new();
+        let mut definitions = Vec::new();
+        let mut current = name.to_string();
+        loop {
+            if chain.contains(&current) {
+                chain.push(current);
+                bail!("profiles の継承が循環しています: {}", chain.join(" -> "));
+            }
+            let definition =
+                self.profile_definition(&current)
+                    .with_context(|| match chain.last() {
+                        Some(child) => format!(
+                            "profiles.{}.inherits のプロファイル {} が定義されていません",
+                            child, current
+                        ),
+                        None => format!(
+                            "プロファイル {} が定義されていません（[profiles.{}] で定義できます）",
+                            current, current
+                        ),
+                    })?;
+            let parent = definition.inherits.clone();
+            chain.push(current);
+            definitions.push(definition);
+            match parent {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+
+        // 継承元から順に重ね、子の設定で上書きする
+        let mut env = BTreeMap::new();
+        for definition in definitions.iter().rev() {
+            env.extend(definition.env.clone().unwrap_or_default());
+        }
+        for (key, value) in env.iter_mut() {
+            *value = template::expand_env(value).with_context(|| {
+                format!("プロファイル {name} の env.{key} の展開に失敗しました")
+            })?;
+        }
+        Ok(ResolvedProfile {
+            name: name.to_string(),
+            chain,
+            env,
+            include: definitions.iter().find_map(|d| d.include.clone()),
+            enabled: definitions.iter().find_map(|d| d.enabled),
+        })
+    }
 }
 
 #[derive(Deserialize)]
 pub struct Project {
     pub name: String,
+    /// 取得元が指定されている場合は、読み込み時に実際のバージョンに置き換えられます。
     pub version: String,
 }
 
+/// `project.version` の取得元
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum VersionSource {
+    From(VersionFrom),
+    File(VersionFile),
+}
+
+#[derive(Deserialize)]
+pub struct VersionFrom {
+    pub from: VersionOrigin,
+    /// バージョンの前に付いている文字列（例: `v`）。git のタグはこれで始まるものだけを使います
+    pub prefix: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum VersionOrigin {
+    #[serde(rename = "Cargo.toml")]
+    CargoToml,
+    #[serde(rename = "package.json")]
+    PackageJson,
+    #[serde(rename = "git-tag")]
+    GitTag,
+}
+
+#[derive(Deserialize)]
+pub struct VersionFile {
+    /// バージョンだけが書かれたファイルのパス
+    pub file: String,
+}
+
 #[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct Artifact {
     pub enabled: Option<bool>,
-    pub source: Option<String>,
+    pub source: Option<ArtifactSource>,
     pub destination: String,
     pub build: Option<BuildCommand>,
     pub placement_method: Option<PlacementMethod>,
     pub profiles: Option<HashMap<String, ArtifactProfile>>,
 }
 
+impl Artifact {
+    /// プロファイルによる上書きを、継承元のプロファイルのものと合わせて反映した設定を返します。
+    /// 上書きはキーごとに行い、`source` などのテーブルも中身を混ぜずに置き換えます。
+    pub fn with_profile(&self, profile: Option<&ResolvedProfile>) -> Result<Artifact> {
+        let (Some(profile), Some(profiles)) = (profile, &self.profiles) else {
+            return Ok(self.clone());
+        };
+        let mut table = toml::Table::try_from(self)?;
+        for overrides in profile
+            .chain
+            .iter()
+            .rev()
+            .filter_map(|name| profiles.get(name))
+        {
+            table.extend(toml::Table::try_from(overrides)?);
+        }
+        Ok(Artifact::deserialize(toml::Value::Table(table))?)
+    }
+
+    /// `headers` の値を伏せた設定を返します。
+    pub fn redacted(&self) -> Artifact {
+        let mut artifact = self.clone();
+        artifact.source = artifact.source.as_ref().map(ArtifactSource::redacted);
+        for profile in artifact.profiles.iter_mut().flat_map(HashMap::values_mut) {
+            profile.source = profile.source.as_ref().map(ArtifactSource::redacted);
+        }
+        artifact
+    }
+}
+
+/// `artifacts.<name>.profiles.<profile>` で上書きできる設定。`profiles` 以外の成果物のキーと同じです。
 #[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct ArtifactProfile {
     pub enabled: Option<bool>,
-    pub source: Option<String>,
+    pub source: Option<ArtifactSource>,
+    pub destination: Option<String>,
     pub build: Option<BuildCommand>,
+    pub placement_method: Option<PlacementMethod>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ArtifactSource {
+    Path(String),
+    Url(UrlSource),
+    GitHub(GitHubSource),
+    Git(GitSource),
+    Project(ProjectSource),
+    Cargo(CargoSource),
+}
+
+impl ArtifactSource {
+    /// `headers` の値を伏せた source を返します。ヘッダーには認証情報が含まれることがあります。
+    pub fn redacted(&self) -> ArtifactSource {
+        let mut source = self.clone();
+        if let ArtifactSource::Url(url) = &mut source {
+            for value in url.headers.iter_mut().flat_map(HashMap::values_mut) {
+                *value = "********".to_string();
+            }
+        }
+        source
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct UrlSource {
+    pub url: String,
+    /// zip 内から取り出すファイルのパス（glob 可）
+    pub path: Option<String>,
+    /// リクエストに付けるヘッダー（`${env:NAME}` で環境変数を参照できます）
+    pub headers: Option<HashMap<String, String>>,
+    pub sha256: Option<String>,
+    pub xxh3: Option<String>,
+    pub cache: Option<CachePolicy>,
+}
+
+/// GitHub のリリースに添付されたファイル
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct GitHubSource {
+    /// `owner/repo`
+    pub github: String,
+    /// リリースのタグ（デフォルトは latest）
+    pub tag: Option<String>,
+    /// 添付ファイル名にマッチする正規表現
+    pub asset: String,
+    pub path: Option<String>,
+    pub sha256: Option<String>,
+    pub xxh3: Option<String>,
+    pub cache: Option<CachePolicy>,
+}
+
+/// git リポジトリ内のファイル
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct GitSource {
+    /// リポジトリの URL またはパス
+    pub git: String,
+    /// コミット・タグ・ブランチ（デフォルトは HEAD）
+    pub rev: Option<String>,
+    /// リポジトリ内のパス
+    pub path: String,
+}
+
+/// 別の au2 プロジェクトの成果物
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct ProjectSource {
+    /// aviutl2.toml があるディレクトリ
+    pub project: String,
+    /// 参照する成果物の名前
+    pub artifact: String,
+}
+
+/// cargo でビルドする Rust の cdylib
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct CargoSource {
+    /// パッケージ名
+    pub cargo: String,
+    /// ターゲットトリプル（例: x86_64-pc-windows-msvc）
+    pub target: Option<String>,
+    /// Cargo.toml のパス
+    pub manifest_path: Option<String>,
+    /// cargo のプロファイル（デフォルトは継承元をたどって debug なら dev、release なら release）
+    pub cargo_profile: Option<String>,
+}
+
+/// HTTP の source のキャッシュの扱い
+#[derive(Clone, Copy, PartialEq)]
+pub enum CachePolicy {
+    /// 一度取得したら `--refresh` するまで使い続ける
+    Forever,
+    /// 毎回条件付きリクエストで更新を確認する
+    Revalidate,
+    /// 指定した期間が過ぎたら条件付きリクエストで更新を確認する
+    Ttl(std::time::Duration),
+}
+
+impl<'de> Deserialize<'de> for CachePolicy {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.as_str() {
+            "forever" => Ok(CachePolicy::Forever),
+            "revalidate" => Ok(CachePolicy::Revalidate),
+            other => other
+                .strip_prefix("ttl:")
+                .and_then(parse_duration)
+                .map(CachePolicy::Ttl)
+                .ok_or_else(|| {
+                    serde::de::Error::custom(format!(
+                        "cache は \"forever\" / \"revalidate\" / \"ttl:<期間>\"（例: ttl:1d）のいずれかです: {other}"
+                    ))
+                }),
+        }
+    }
+}
+
+impl Serialize for CachePolicy {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            CachePolicy::Forever => serializer.serialize_str("forever"),
+            CachePolicy::Revalidate => serializer.serialize_str("revalidate"),
+            CachePolicy::Ttl(duration) => {
+                serializer.serialize_str(&format!("ttl:{}s", duration.as_secs()))
+            }
+        }
+    }
+}
+
+/// `30s` / `10m` / `12h` / `1d` / `2w` 形式の期間を解釈します。
+pub fn parse_duration(input: &str) -> Option<std::time::Duration> {
+    let input = input.trim();
+    let split = input.find(|c: char| !c.is_ascii_digit())?;
+    let (value, unit) = input.split_at(split);
+    let value: u64 = value.parse().ok()?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => return None,
+    };
+    Some(std::time::Duration::from_secs(value.checked_mul(seconds)?))
 }
 
 #[derive(Clone, Copy, Deserialize, Serialize, Eq, PartialEq)]
@@ -60,17 +374,66 @@ pub struct BuildGroupRef {
 
 #[derive(Deserialize)]
 pub struct Development {
-    pub aviutl2_version: String,
+    pub aviutl2_version: Aviutl2Version,
     pub install_dir: Option<String>,
+    pub api_base: Option<ApiBase>,
+    pub preserve: Option<Vec<String>>,
     pub profile: Option<String>,
     pub prebuild: Option<BuildCommand>,
     pub postbuild: Option<BuildCommand>,
 }
 
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Aviutl2Version {
+    Release(String),
+    Pinned(Aviutl2Release),
+    Archive(Aviutl2Archive),
+    Directory(Aviutl2Directory),
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct Aviutl2Release {
+    pub version: String,
+    pub sha256: Option<String>,
+    pub xxh3: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct Aviutl2Archive {
+    pub path: String,
+    pub sha256: Option<String>,
+    pub xxh3: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct Aviutl2Directory {
+    pub dir: String,
+}
+
+impl std::fmt::Display for Aviutl2Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Aviutl2Version::Release(version) => write!(f, "{version}"),
+            Aviutl2Version::Pinned(release) => write!(f, "{}", release.version),
+            Aviutl2Version::Archive(archive) => write!(f, "{}", archive.path),
+            Aviutl2Version::Directory(directory) => write!(f, "{}", directory.dir),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ApiBase {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
 #[derive(Deserialize)]
 pub struct Preview {
-    pub aviutl2_version: Option<String>,
+    pub aviutl2_version: Option<Aviutl2Version>,
     pub install_dir: Option<String>,
+    pub preserve: Option<Vec<String>>,
     pub profile: Option<String>,
     pub include: Option<Vec<String>>,
     pub prebuild: Option<BuildCommand>,
@@ -161,7 +524,6 @@ impl Serialize for CatalogType {
     }
 }
 
-
 #[derive(Deserialize, Serialize, Clone, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum CatalogLicenseText {
@@ -313,23 +675,258 @@ pub enum CatalogAction {
 
 pub fn load_config() -> Result<Config> {
     let path = find_config_path()?;
-    let content = fs::read_to_string(&path)
-        .with_context(|| format!("設定ファイルの読み込みに失敗しました: {}", path.display()))?;
-    toml::from_str(&content).with_context(|| "設定ファイルの解析に失敗しました")
+    read_config(&path)
+}
+
+/// 設定ファイルがあれば読み込みます。見つからない場合は `None` を返します。
+pub fn load_config_if_exists() -> Result<Option<Config>> {
+    let Ok(path) = find_config_path() else {
+        return Ok(None);
+    };
+    read_config(&path).map(Some)
+}
+
+pub fn read_config(path: &Path) -> Result<Config> {
+    let mut layered = read_layered_config(path)?;
+    for (layer, table) in &layered.layers {
+        warn_unknown_keys(layer, table);
+    }
+    // project はすべてのコマンドで使うので読み込み時に展開し、それ以外は使う時点で展開する。
+    // こうすると、使わない値の環境変数が設定されていなくてもエラーにならない
+    template::expand_config(&mut layered.table, "project")?;
+    resolve_project_version(&mut layered.table, path)?;
+    // 新しい形式の設定ファイルは解析に失敗しやすいので、先にバージョンを確認する
+    match layered
+        .table
+        .get("schema_version")
+        .and_then(toml::Value::as_integer)
+    {
+        Some(version) if version > i64::from(SCHEMA_VERSION) => bail!(
+            "{} は新しい形式（schema_version = {}）で書かれています。au2 を更新してください",
+            path.display(),
+            version
+        ),
+        Some(version) if version < i64::from(SCHEMA_VERSION) => log::warn!(
+            "{} は古い形式（schema_version = {}）です。`au2 migrate` で更新できます",
+            path.display(),
+            version
+        ),
+        Some(_) => {}
+        // schema_version がない設定ファイルは最も古い形式
+        None => log::warn!(
+            "{} に schema_version がないため古い形式として扱います。`au2 migrate` で更新できます",
+            path.display()
+        ),
+    }
+    Config::deserialize(toml::Value::Table(layered.table)).with_context(
+        || "設定ファイルの解析に失敗しました（`au2 check` で問題のある場所を確認できます）",
+    )
+}
+
+/// 設定ファイルと、その上に重ねたローカルの設定ファイルを結合した内容
+pub struct LayeredConfig {
+    pub table: toml::Table,
+    /// 値ごとに、どのファイルから来たか
+    pub origins: BTreeMap<Vec<String>, PathBuf>,
+    /// 結合する前の各ファイルの内容
+    pub layers: Vec<(PathBuf, toml::Table)>,
+}
+
+impl LayeredConfig {
+    /// `path` の値がどのファイルから来たかを返します。
+    pub fn origin(&self, path: &[String]) -> Option<&Path> {
+        self.origins.get(path).map(PathBuf::as_path)
+    }
 }
 
-fn find_config_path() -> Result<PathBuf> {
-    let local = PathBuf::from("aviutl2.toml");
-    if local.exists() {
-        return Ok(local);
+/// `aviutl2.toml` に対する `aviutl2.local.toml` のように、コミットしない個人用の設定ファイルのパスを返します。
+pub fn local_config_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("aviutl2");
+    path.with_file_name(format!("{stem}.local.toml"))
+}
+
+/// 設定ファイルを読み込み、ローカルの設定ファイルがあればテーブルごとに再帰的に上書きします。
+/// テーブル以外の値（配列を含む）はローカルの設定ファイルの値で置き換えます。
+pub fn read_layered_config(path: &Path) -> Result<LayeredConfig> {
+    let mut layered = LayeredConfig {
+        table: toml::Table::new(),
+        origins: BTreeMap::new(),
+        layers: Vec::new(),
+    };
+    let local_path = local_config_path(path);
+    let layers = std::iter::once(path).chain(local_path.is_file().then_some(local_path.as_path()));
+    for layer in layers {
+        let content = fs::read_to_string(layer).with_context(|| {
+            format!("設定ファイルの読み込みに失敗しました: {}", layer.display())
+        })?;
+        let table = toml::from_str::<toml::Table>(&content)
+            .with_context(|| format!("設定ファイルの解析に失敗しました: {}", layer.display()))?;
+        layered.layers.push((layer.to_path_buf(), table.clone()));
+        merge_table(
+            &mut layered.table,
+            table,
+            &mut Vec::new(),
+            &mut layered.origins,
+            layer,
+        );
     }
-    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
-        .map(PathBuf::from)
-        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
-        .context("設定ファイルが見つかりません")?;
-    let path = config_dir.join("aviutl2.toml");
-    if path.exists() {
-        return Ok(path);
+    Ok(layered)
+}
+
+fn merge_table(
+    base: &mut toml::Table,
+    overlay: toml::Table,
+    path: &mut Vec<String>,
+    origins: &mut BTreeMap<Vec<String>, PathBuf>,
+    origin: &Path,
+) {
+    for (key, value) in overlay {
+        path.push(key.clone());
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_table(base, overlay, path, origins, origin);
+            }
+            (_, value) => {
+                origins.retain(|key, _| !key.starts_with(path));
+                record_origins(&value, path, origins, origin);
+                base.insert(key, value);
+            }
+        }
+        path.pop();
+    }
+}
+
+fn record_origins(
+    value: &toml::Value,
+    path: &mut Vec<String>,
+    origins: &mut BTreeMap<Vec<String>, PathBuf>,
+    origin: &Path,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                path.push(key.clone());
+                record_origins(value, path, origins, origin);
+                path.pop();
+            }
+        }
+        _ => {
+            origins.insert(path.clone(), origin.to_path_buf());
+        }
+    }
+}
+
+/// 綴りを間違えたキーが黙って無視されないよう、スキーマにないキーを警告します。
+/// 同じファイルを何度も読み込む場合があるので、警告はファイルごとに一度だけ出します。
+fn warn_unknown_keys(path: &Path, table: &toml::Table) {
+    static WARNED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+    let Ok(value) = serde_json::to_value(table) else {
+        return;
+    };
+    let unknown = schema::unknown_keys(&value);
+    if unknown.is_empty() {
+        return;
+    }
+    let mut warned = WARNED.lock().unwrap_or_else(|err| err.into_inner());
+    if warned.iter().any(|warned| warned == path) {
+        return;
+    }
+    warned.push(path.to_path_buf());
+    for key in unknown {
+        log::warn!(
+            "{}: {}: {}",
+            path.display(),
+            schema::key_path(&key.path),
+            key.message()
+        );
+    }
+}
+
+/// `enter_project` で見つけた設定ファイルのパス（プロジェクトのディレクトリからの相対パス）
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// 設定ファイルを探し、そのプロジェクトのディレクトリをカレントディレクトリにします。
+/// 以降の相対パス（`.aviutl2-cli` や成果物のパスなど）はすべてプロジェクトのディレクトリが基準になります。
+/// `explicit` がなければカレントディレクトリから親へ順に探し、見つからなければ何もしません。
+pub fn enter_project(explicit: Option<&Path>) -> Result<()> {
+    let path = match explicit {
+        Some(path) => {
+            if !path.is_file() {
+                bail!("設定ファイルが見つかりません: {}", path.display());
+            }
+            std::path::absolute(path)?
+        }
+        None => {
+            let current_dir =
+                std::env::current_dir().context("カレントディレクトリの取得に失敗しました")?;
+            match current_dir.ancestors().find_map(config_file_in) {
+                Some(path) => path,
+                None => return Ok(()),
+            }
+        }
+    };
+    let root = project_root_of(&path);
+    std::env::set_current_dir(&root).with_context(|| {
+        format!(
+            "プロジェクトのディレクトリに移動できません: {}",
+            root.display()
+        )
+    })?;
+    log::debug!("プロジェクトのディレクトリ: {}", root.display());
+    let relative = path.strip_prefix(&root).unwrap_or(&path).to_path_buf();
+    let _ = CONFIG_PATH.set(relative);
+    Ok(())
+}
+
+/// `dir` にある設定ファイル（`aviutl2.toml` または `.config/aviutl2.toml`）を返します。
+pub fn config_file_in(dir: &Path) -> Option<PathBuf> {
+    [
+        dir.join("aviutl2.toml"),
+        dir.join(".config").join("aviutl2.toml"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// `project.version` に取得元が指定されていれば、読み取ったバージョンに置き換えます。
+pub fn resolve_project_version(table: &mut toml::Table, config_path: &Path) -> Result<()> {
+    let Some(version) = table
+        .get_mut("project")
+        .and_then(toml::Value::as_table_mut)
+        .and_then(|project| project.get_mut("version"))
+        .filter(|version| version.is_table())
+    else {
+        return Ok(());
+    };
+    let source = VersionSource::deserialize(version.clone())
+        .context("project.version の解析に失敗しました")?;
+    let root = match project_root_of(config_path) {
+        root if root.as_os_str().is_empty() => PathBuf::from("."),
+        root => root,
+    };
+    let resolved =
+        version::resolve(&source, &root).context("project.version の取得に失敗しました")?;
+    *version = toml::Value::String(resolved);
+    Ok(())
+}
+
+/// 設定ファイルが `.config` の中にある場合は、その親をプロジェクトのディレクトリとします。
+fn project_root_of(config_path: &Path) -> PathBuf {
+    let dir = config_path.parent().unwrap_or(Path::new(""));
+    match dir.parent() {
+        Some(parent) if dir.file_name().is_some_and(|name| name == ".config") => {
+            parent.to_path_buf()
+        }
+        _ => dir.to_path_buf(),
+    }
+}
+
+pub fn find_config_path() -> Result<PathBuf> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return Ok(path.clone());
     }
-    bail!("aviutl2.toml が見つかりません");
+    config_file_in(Path::new("")).context("aviutl2.toml が見つかりません")
 }
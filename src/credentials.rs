@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use fs_err as fs;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::template::expand_env;
+
+const CREDENTIALS_ENV: &str = "AU2_CREDENTIALS";
+
+/// ユーザーごとの認証情報（`~/.config/au2/credentials.toml`）。
+///
+/// ```toml
+/// [hosts."files.example.com"]
+/// token = "${env:EXAMPLE_TOKEN}"
+///
+/// [hosts."files.example.com".headers]
+/// X-Api-Key = "..."
+/// ```
+#[derive(Default, Deserialize)]
+struct Credentials {
+    #[serde(default)]
+    hosts: HashMap<String, HostCredential>,
+}
+
+#[derive(Deserialize)]
+struct HostCredential {
+    /// `Authorization: Bearer <token>` として送ります
+    token: Option<String>,
+    headers: Option<HashMap<String, String>>,
+}
+
+/// `url` へのリクエストに付けるヘッダーを求めます。
+/// 認証情報ファイルのホストごとの設定に、source の `headers` を上書きして返します。
+/// 値は秘密情報を含みうるため、ログには出力しないでください。
+pub fn request_headers(
+    url: &str,
+    source_headers: Option<&HashMap<String, String>>,
+) -> Result<Vec<(String, String)>> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut set = |name: &str, value: String| {
+        headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+        headers.push((name.to_string(), value));
+    };
+    if let Some(host) = host_of(url) {
+        let credentials = load_credentials()?;
+        let credential = credentials.hosts.get(host).or_else(|| {
+            host.rsplit_once(':')
+                .and_then(|(name, _)| credentials.hosts.get(name))
+        });
+        if let Some(credential) = credential {
+            if let Some(token) = &credential.token {
+                let token = expand_env(token)
+                    .with_context(|| format!("{host} の認証情報の展開に失敗しました"))?;
+                set("Authorization", format!("Bearer {token}"));
+            }
+            for (name, value) in sorted(credential.headers.as_ref()) {
+                let value = expand_env(value).with_context(|| {
+                    format!("{host} の認証情報のヘッダー {name} の展開に失敗しました")
+                })?;
+                set(name, value);
+            }
+        }
+    }
+    for (name, value) in sorted(source_headers) {
+        let value = expand_env(value)
+            .with_context(|| format!("source のヘッダー {name} の展開に失敗しました"))?;
+        set(name, value);
+    }
+    Ok(headers)
+}
+
+fn sorted(headers: Option<&HashMap<String, String>>) -> Vec<(&String, &String)> {
+    let mut headers = headers
+        .map(|headers| headers.iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+    headers.sort();
+    headers
+}
+
+fn load_credentials() -> Result<Credentials> {
+    let Some(path) = credentials_path() else {
+        return Ok(Credentials::default());
+    };
+    if !path.exists() {
+        return Ok(Credentials::default());
+    }
+    let content = fs::read_to_string(&path)?;
+    toml::from_str(&content)
+        .with_context(|| format!("認証情報ファイルの解析に失敗しました: {}", path.display()))
+}
+
+/// `AU2_CREDENTIALS` で場所を変更できます。
+fn credentials_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CREDENTIALS_ENV).filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    }?;
+    Some(config_dir.join("au2").join("credentials.toml"))
+}
+
+/// URL のホスト部分（ポートがあれば含む）
+fn host_of(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    (!host.is_empty()).then_some(host)
+}
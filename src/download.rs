@@ -0,0 +1,418 @@
+use anyhow::{Context, Result, anyhow};
+use fs_err as fs;
+use fs_err::{File, OpenOptions};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::util::{Checksum, remove_path, verify_checksum};
+
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+pub struct DownloadRequest<'a> {
+    pub url: &'a str,
+    pub query: &'a [(&'a str, &'a str)],
+    /// 認証などの追加のヘッダー。秘密情報を含みうるためログには出力しません
+    pub headers: &'a [(String, String)],
+    /// 指定すると条件付きリクエストを送り、変更がなければダウンロードしません
+    pub validators: Option<&'a Validators>,
+    /// 指定すると、ダウンロードしたファイルを `destination` に移す前に検証します
+    pub checksum: Checksum<'a>,
+}
+
+impl<'a> DownloadRequest<'a> {
+    pub fn new(url: &'a str) -> Self {
+        Self {
+            url,
+            query: &[],
+            headers: &[],
+            validators: None,
+            checksum: Checksum::default(),
+        }
+    }
+}
+
+/// 条件付きリクエストに使うレスポンスヘッダーの値。
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    fn from_headers(headers: &ureq::http::HeaderMap) -> Self {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: get("ETag"),
+            last_modified: get("Last-Modified"),
+        }
+    }
+}
+
+pub enum DownloadOutcome {
+    Downloaded(Validators),
+    NotModified,
+}
+
+enum Failure {
+    /// 再試行すれば成功する可能性があるエラー
+    Transient(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+/// `request` の内容を `destination` に保存します。
+/// 途中までのデータは `<destination>.partial` に書き込み、中断された場合は Range リクエストで続きから再開します。
+/// 再開時は前回のレスポンスの ETag / Last-Modified を If-Range で送り、内容が変わっていれば最初から取得し直します。
+/// 一時的なエラーは間隔を空けて再試行します。検証に失敗した場合は `destination` を置き換えません。
+pub fn download_file(request: &DownloadRequest, destination: &Path) -> Result<DownloadOutcome> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = partial_path(destination);
+    let agent = agent();
+
+    let mut attempt = 1;
+    let validators = loop {
+        match try_download(&agent, request, &partial) {
+            Ok(Some(validators)) => break validators,
+            Ok(None) => return Ok(DownloadOutcome::NotModified),
+            Err(Failure::Transient(err)) if attempt < MAX_ATTEMPTS => {
+                let wait = INITIAL_BACKOFF * 2u32.pow(attempt - 1);
+                log::warn!(
+                    "ダウンロードに失敗したため {:.1} 秒後に再試行します（{}/{}）: {:#}",
+                    wait.as_secs_f32(),
+                    attempt,
+                    MAX_ATTEMPTS - 1,
+                    err
+                );
+                std::thread::sleep(wait);
+                attempt += 1;
+            }
+            Err(Failure::Transient(err)) | Err(Failure::Fatal(err)) => {
+                return Err(err.context(format!("ダウンロードに失敗しました: {}", request.url)));
+            }
+        }
+    };
+
+    remove_path(&resume_path(&partial))?;
+    if let Err(err) = verify_checksum(&partial, &request.checksum) {
+        remove_path(&partial)?;
+        return Err(err.context(format!(
+            "ダウンロードしたファイルの検証に失敗しました: {}",
+            request.url
+        )));
+    }
+    remove_path(destination)?;
+    fs::rename(&partial, destination)?;
+    Ok(DownloadOutcome::Downloaded(validators))
+}
+
+/// リダイレクトと環境変数のプロキシ設定を有効にした HTTP クライアントを作成します。
+pub fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .max_redirects(5)
+        .proxy(ureq::Proxy::try_from_env())
+        .build()
+        .into()
+}
+
+/// `url` から JSON を取得します。一時的なエラーは `download_file` と同様に再試行します。
+pub fn fetch_json<T: serde::de::DeserializeOwned>(
+    url: &str,
+    headers: &[(&str, &str)],
+) -> Result<T> {
+    let agent = agent();
+    let mut attempt = 1;
+    loop {
+        let mut builder = agent
+            .get(url)
+            .header("User-Agent", "aviutl2-cli")
+            .header("Accept", "application/json");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let result = builder.call();
+        let failure = match result {
+            Ok(response) => {
+                let (_parts, body) = response.into_parts();
+                return serde_json::from_reader(body.into_reader())
+                    .with_context(|| format!("JSON の解析に失敗しました: {url}"));
+            }
+            Err(err) => classify(err),
+        };
+        match failure {
+            Failure::Transient(err) if attempt < MAX_ATTEMPTS => {
+                let wait = INITIAL_BACKOFF * 2u32.pow(attempt - 1);
+                log::warn!(
+                    "取得に失敗したため {:.1} 秒後に再試行します（{}/{}）: {:#}",
+                    wait.as_secs_f32(),
+                    attempt,
+                    MAX_ATTEMPTS - 1,
+                    err
+                );
+                std::thread::sleep(wait);
+                attempt += 1;
+            }
+            Failure::Transient(err) | Failure::Fatal(err) => {
+                return Err(err.context(format!("取得に失敗しました: {url}")));
+            }
+        }
+    }
+}
+
+pub fn partial_path(destination: &Path) -> PathBuf {
+    let mut name = destination
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(".partial");
+    destination.with_file_name(name)
+}
+
+/// `.partial` を再開するための情報を保存するパス
+fn resume_path(partial: &Path) -> PathBuf {
+    let mut name = partial
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(".json");
+    partial.with_file_name(name)
+}
+
+/// `.partial` を取得したときの URL とレスポンスの検証子。
+#[derive(Serialize, Deserialize)]
+struct ResumeInfo {
+    url: String,
+    #[serde(flatten)]
+    validators: Validators,
+}
+
+impl ResumeInfo {
+    /// If-Range に使う値。弱い ETag は If-Range に使えないので Last-Modified を使います。
+    fn if_range(&self) -> Option<&str> {
+        self.validators
+            .etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.validators.last_modified.as_deref())
+    }
+}
+
+/// `.partial` の続きから再開できる場合は、その位置と If-Range の値を返します。
+/// 再開できない `.partial` は削除します。
+fn resume_point(request: &DownloadRequest, partial: &Path) -> Result<Option<(u64, String)>> {
+    let offset = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+    if offset == 0 {
+        return Ok(None);
+    }
+    let info = fs::read_to_string(resume_path(partial))
+        .ok()
+        .and_then(|content| serde_json::from_str::<ResumeInfo>(&content).ok());
+    match info {
+        Some(info) if info.url == request.url => {
+            if let Some(if_range) = info.if_range() {
+                return Ok(Some((offset, if_range.to_string())));
+            }
+        }
+        _ => {}
+    }
+    log::debug!(
+        "途中までのデータを再開できないため破棄します: {}",
+        partial.display()
+    );
+    remove_path(partial)?;
+    remove_path(&resume_path(partial))?;
+    Ok(None)
+}
+
+/// 変更がなかった場合は `Ok(None)` を返します。
+fn try_download(
+    agent: &ureq::Agent,
+    request: &DownloadRequest,
+    partial: &Path,
+) -> std::result::Result<Option<Validators>, Failure> {
+    let resume = resume_point(request, partial).map_err(Failure::Fatal)?;
+    let offset = resume.as_ref().map_or(0, |(offset, _)| *offset);
+    let mut builder = agent.get(request.url).header("User-Agent", "aviutl2-cli");
+    for (key, value) in request.query {
+        builder = builder.query(*key, *value);
+    }
+    for (name, value) in request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(validators) = request.validators {
+        if let Some(etag) = &validators.etag {
+            builder = builder.header("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            builder = builder.header("If-Modified-Since", last_modified);
+        }
+    }
+    if let Some((offset, if_range)) = &resume {
+        builder = builder
+            .header("Range", format!("bytes={offset}-"))
+            .header("If-Range", if_range);
+    }
+    let response = match builder.call() {
+        Ok(response) => response,
+        Err(ureq::Error::StatusCode(416)) if offset > 0 => {
+            // 途中のファイルが壊れているので最初からやり直す
+            remove_path(partial).map_err(Failure::Fatal)?;
+            return Err(Failure::Transient(anyhow!(
+                "途中までのデータを再開できませんでした"
+            )));
+        }
+        Err(err) => return Err(classify(err)),
+    };
+    if response.status() == 304 {
+        return Ok(None);
+    }
+    let validators = Validators::from_headers(response.headers());
+
+    let resumed = offset > 0
+        && response.status() == 206
+        && content_range_start(response.headers()) == Some(offset);
+    if response.status() == 206 && !resumed {
+        // 要求した位置以外の部分が返ってきたので、途中までのデータを捨てて Range なしで取得し直す
+        remove_path(partial).map_err(Failure::Fatal)?;
+        remove_path(&resume_path(partial)).map_err(Failure::Fatal)?;
+        return Err(Failure::Transient(anyhow!(
+            "要求した位置と異なる範囲が返されました"
+        )));
+    }
+    let content_length = response
+        .headers()
+        .get("Content-Length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let (start, mut file) = if resumed {
+        log::info!(
+            "ダウンロードを再開します: {} ({} バイトから)",
+            request.url,
+            offset
+        );
+        let file = OpenOptions::new()
+            .append(true)
+            .open(partial)
+            .map_err(|err| Failure::Fatal(err.into()))?;
+        (offset, file)
+    } else {
+        // If-Range の検証子が一致しない場合は 200 で全体が返ってくるので最初から書き直す
+        let file = File::create(partial).map_err(|err| Failure::Fatal(err.into()))?;
+        let info = ResumeInfo {
+            url: request.url.to_string(),
+            validators: validators.clone(),
+        };
+        let content = serde_json::to_string(&info).map_err(|err| Failure::Fatal(err.into()))?;
+        fs::write(resume_path(partial), content).map_err(|err| Failure::Fatal(err.into()))?;
+        (0, file)
+    };
+
+    let progress = progress_bar(request.url, content_length.map(|len| len + start));
+    progress.set_position(start);
+    let (_parts, body) = response.into_parts();
+    let mut reader = body.into_reader();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                progress.abandon();
+                return Err(Failure::Transient(err.into()));
+            }
+        };
+        file.write_all(&buf[..read])
+            .map_err(|err| Failure::Fatal(err.into()))?;
+        progress.inc(read as u64);
+    }
+    file.flush().map_err(|err| Failure::Fatal(err.into()))?;
+    progress.finish_and_clear();
+
+    if let Some(total) = content_length.map(|len| len + start) {
+        let written = fs::metadata(partial)
+            .map_err(|err| Failure::Fatal(err.into()))?
+            .len();
+        if written != total {
+            return Err(Failure::Transient(anyhow!(
+                "受信したサイズが一致しません（{written} / {total} バイト）"
+            )));
+        }
+    }
+    Ok(Some(validators))
+}
+
+fn classify(err: ureq::Error) -> Failure {
+    match err {
+        ureq::Error::StatusCode(code) if code == 429 || code >= 500 => {
+            Failure::Transient(anyhow!("サーバーがエラーを返しました: {code}"))
+        }
+        ureq::Error::StatusCode(code) => {
+            Failure::Fatal(anyhow!("サーバーがエラーを返しました: {code}"))
+        }
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::BodyStalled
+        | ureq::Error::Protocol(_) => Failure::Transient(err.into()),
+        err => Failure::Fatal(err.into()),
+    }
+}
+
+fn content_range_start(headers: &ureq::http::HeaderMap) -> Option<u64> {
+    let value = headers.get("Content-Range")?.to_str().ok()?;
+    let range = value.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+fn progress_bar(url: &str, total: Option<u64>) -> ProgressBar {
+    let target = if std::io::stderr().is_terminal() {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    };
+    let name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|url| url.rsplit('/').find(|part| !part.is_empty()))
+        .unwrap_or(url)
+        .to_string();
+    match total {
+        Some(total) => {
+            let progress = ProgressBar::with_draw_target(Some(total), target);
+            progress.set_style(
+                ProgressStyle::with_template(
+                    "{msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, 残り {eta})",
+                )
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+                .progress_chars("=> "),
+            );
+            progress.set_message(name);
+            progress
+        }
+        None => {
+            let progress = ProgressBar::with_draw_target(None, target);
+            progress.set_style(
+                ProgressStyle::with_template("{spinner} {msg} {bytes} ({bytes_per_sec})")
+                    .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+            );
+            progress.set_message(name);
+            progress
+        }
+    }
+}
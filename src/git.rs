@@ -0,0 +1,217 @@
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::GitSource;
+use crate::lockfile::Lockfile;
+use crate::util::{git_dir, remove_path, safe_join};
+
+/// git リポジトリの source を取得し、指定したファイルのパスを返します。
+/// 解決したコミットは `root` のプロジェクトの `aviutl2.lock` に記録し、`--refresh` するまでそのコミットを使い続けます。
+pub fn resolve(source: &GitSource, root: &Path, refresh: bool) -> Result<PathBuf> {
+    let url = source.git.as_str();
+    let rev = source.rev.as_deref().unwrap_or("HEAD");
+    let key = format!("{:016x}", xxhash_rust::xxh3::xxh3_64(url.as_bytes()));
+    let git_dir = git_dir(root)?;
+    let repo = git_dir.join("repos").join(format!("{key}.git"));
+    let remote = remote_url(url, root);
+    let remote = remote.as_str();
+
+    let mut lockfile = Lockfile::load(root)?;
+    let locked = if refresh {
+        None
+    } else {
+        lockfile.git_commit(url, rev).map(str::to_string)
+    };
+    let commit = match locked {
+        Some(commit) => {
+            if !repo.exists() {
+                clone(remote, &repo)?;
+            }
+            if !has_commit(&repo, &commit) {
+                fetch(remote, &repo)?;
+                if !has_commit(&repo, &commit) {
+                    // ブランチやタグから辿れなくなったコミットも直接取得を試みる
+                    let _ = fetch_commit(remote, &repo, &commit);
+                }
+                if !has_commit(&repo, &commit) {
+                    bail!(
+                        "aviutl2.lock に記録されたコミットが見つかりません: {} ({})\n  --refresh で解決し直せます",
+                        url,
+                        commit
+                    );
+                }
+            }
+            commit
+        }
+        None => {
+            if repo.exists() {
+                fetch(remote, &repo)?;
+            } else {
+                clone(remote, &repo)?;
+            }
+            let commit = match rev_parse(&repo, rev) {
+                Ok(commit) => commit,
+                // ブランチやタグから辿れないコミットはハッシュを指定して取得する
+                Err(_) if is_commit_hash(rev) && fetch_commit(remote, &repo, rev).is_ok() => {
+                    rev_parse(&repo, rev)?
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("リビジョンが見つかりません: {url} ({rev})"));
+                }
+            };
+            lockfile.set_git_commit(url, rev, &commit);
+            lockfile.save(root)?;
+            commit
+        }
+    };
+
+    let checkout = git_dir.join("checkouts").join(&key).join(&commit);
+    if !checkout.join(".git").exists() {
+        remove_path(&checkout)?;
+        git(&repo, &["worktree", "prune"])?;
+        let checkout_str = checkout.to_string_lossy();
+        git(
+            &repo,
+            &[
+                "worktree",
+                "add",
+                "--force",
+                "--detach",
+                &checkout_str,
+                &commit,
+            ],
+        )?;
+        log::info!(
+            "git の source をチェックアウトしました: {} ({})",
+            url,
+            commit
+        );
+    }
+    let path = safe_join(&checkout, &source.path)?;
+    if !path.exists() {
+        bail!(
+            "リポジトリ内にファイルがありません: {} ({} {})",
+            source.path,
+            url,
+            commit
+        );
+    }
+    Ok(path)
+}
+
+/// ローカルのリポジトリを相対パスで指定した場合は、`root` からのパスにします。
+fn remote_url(url: &str, root: &Path) -> String {
+    let scp_like = url
+        .split_once(':')
+        .is_some_and(|(host, _)| !host.contains(['/', '\\']));
+    if url.contains("://") || scp_like || Path::new(url).is_absolute() {
+        url.to_string()
+    } else {
+        root.join(url).to_string_lossy().into_owned()
+    }
+}
+
+fn clone(url: &str, repo: &Path) -> Result<()> {
+    log::info!("git リポジトリを取得します: {}", url);
+    remove_path(repo)?;
+    if let Some(parent) = repo.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    let status = Command::new("git")
+        .args(["clone", "--bare", "--quiet", "--", url])
+        .arg(repo)
+        .status()
+        .context("git コマンドの実行に失敗しました")?;
+    if !status.success() {
+        bail!("git リポジトリの取得に失敗しました: {}", url);
+    }
+    Ok(())
+}
+
+fn fetch(url: &str, repo: &Path) -> Result<()> {
+    log::info!("git リポジトリを更新します: {}", url);
+    git(
+        repo,
+        &[
+            "fetch",
+            "--quiet",
+            "--prune",
+            "--force",
+            "--",
+            url,
+            "+refs/heads/*:refs/heads/*",
+            "+refs/tags/*:refs/tags/*",
+        ],
+    )
+    .with_context(|| format!("git リポジトリの更新に失敗しました: {url}"))?;
+    Ok(())
+}
+
+/// ブランチやタグに含まれないコミットを取得します。サーバーが対応していない場合は失敗します。
+fn fetch_commit(url: &str, repo: &Path, commit: &str) -> Result<()> {
+    log::info!("git のコミットを取得します: {} ({})", url, commit);
+    git(repo, &["fetch", "--quiet", "--", url, commit])?;
+    Ok(())
+}
+
+/// 省略していないコミットのハッシュ（SHA-1 または SHA-256）かどうか
+fn is_commit_hash(rev: &str) -> bool {
+    matches!(rev.len(), 40 | 64) && rev.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn has_commit(repo: &Path, commit: &str) -> bool {
+    git(repo, &["cat-file", "-e", &format!("{commit}^{{commit}}")]).is_ok()
+}
+
+fn rev_parse(repo: &Path, rev: &str) -> Result<String> {
+    git(
+        repo,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{rev}^{{commit}}"),
+        ],
+    )
+}
+
+/// `dir` の作業ツリーで、HEAD から辿れる最新のタグを返します。`prefix` があればそれで始まるタグだけを探します。
+pub fn latest_tag(dir: &Path, prefix: Option<&str>) -> Result<String> {
+    let mut command = Command::new("git");
+    command
+        .current_dir(dir)
+        .args(["describe", "--tags", "--abbrev=0"]);
+    if let Some(prefix) = prefix {
+        command.arg(format!("--match={prefix}*"));
+    }
+    let output = command
+        .output()
+        .context("git コマンドの実行に失敗しました")?;
+    if !output.status.success() {
+        bail!(
+            "バージョンを表す git のタグが見つかりません: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `repo` を対象に git を実行し、標準出力を返します。
+fn git(repo: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo)
+        .args(args)
+        .output()
+        .context("git コマンドの実行に失敗しました")?;
+    if !output.status.success() {
+        bail!(
+            "git {} が失敗しました: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,172 @@
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::cache;
+use crate::config::GitHubSource;
+use crate::credentials::request_headers;
+use crate::download::fetch_json;
+
+const DEFAULT_API_BASE: &str = "https://api.github.com";
+const API_BASE_ENV: &str = "AU2_GITHUB_API_BASE";
+const TOKEN_ENV: &str = "GITHUB_TOKEN";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    /// API 経由でダウンロードする URL（非公開リポジトリ用）
+    url: String,
+    browser_download_url: String,
+}
+
+/// GitHub のリリースから `asset` に一致する添付ファイルを探し、ダウンロードする URL とヘッダーを返します。
+/// タグを固定している場合は一度解決した URL を記録しておき、`--refresh` するまで API を呼びません。
+pub fn resolve_asset(
+    source: &GitHubSource,
+    refresh: bool,
+) -> Result<(String, Vec<(String, String)>)> {
+    let tag = source.tag.as_deref().unwrap_or("latest");
+    let key = format!("{}@{}#{}", source.github, tag, source.asset);
+    let recorded = cache::recorded_release_asset(&key)?;
+    let url = if tag != "latest"
+        && !refresh
+        && let Some(url) = recorded
+    {
+        url
+    } else {
+        match fetch_asset_url(source, tag) {
+            Ok(url) => {
+                cache::record_release_asset(&key, &url)?;
+                url
+            }
+            Err(err) => match recorded {
+                Some(url) => {
+                    log::warn!(
+                        "GitHub のリリース情報を取得できなかったため前回の結果を使います: {}: {:#}",
+                        source.github,
+                        err
+                    );
+                    url
+                }
+                None => return Err(err),
+            },
+        }
+    };
+    let headers = if url.starts_with(&api_base()) {
+        let mut headers = api_headers(&url)?;
+        headers.push(("Accept".to_string(), "application/octet-stream".to_string()));
+        headers
+    } else {
+        request_headers(&url, None)?
+    };
+    Ok((url, headers))
+}
+
+fn fetch_asset_url(source: &GitHubSource, tag: &str) -> Result<String> {
+    let Some((owner, repo)) = source
+        .github
+        .split_once('/')
+        .filter(|(owner, repo)| !owner.is_empty() && !repo.is_empty() && !repo.contains('/'))
+    else {
+        bail!(
+            "github は owner/repo の形式で指定してください: {}",
+            source.github
+        );
+    };
+    let pattern = Regex::new(&source.asset)
+        .with_context(|| format!("asset の正規表現が不正です: {}", source.asset))?;
+
+    let api_base = api_base();
+    let url = if tag == "latest" {
+        format!("{api_base}/repos/{owner}/{repo}/releases/latest")
+    } else {
+        format!("{api_base}/repos/{owner}/{repo}/releases/tags/{tag}")
+    };
+    let headers = api_headers(&url)?;
+    let headers = headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect::<Vec<_>>();
+    let release: Release = fetch_json(&url, &headers).with_context(|| {
+        format!(
+            "GitHub のリリース情報の取得に失敗しました: {} ({})",
+            source.github, tag
+        )
+    })?;
+
+    let matched = release
+        .assets
+        .iter()
+        .filter(|asset| pattern.is_match(&asset.name))
+        .collect::<Vec<_>>();
+    match matched.as_slice() {
+        [asset] => {
+            log::info!(
+                "GitHub のリリースの添付ファイルを使います: {} {} {}",
+                source.github,
+                release.tag_name,
+                asset.name
+            );
+            // トークンがあれば非公開リポジトリでも取得できるよう API 経由でダウンロードする
+            if github_token().is_some() {
+                Ok(asset.url.clone())
+            } else {
+                Ok(asset.browser_download_url.clone())
+            }
+        }
+        [] => bail!(
+            "asset に一致する添付ファイルがありません: {} ({} {})\n  添付ファイル: {}",
+            source.asset,
+            source.github,
+            release.tag_name,
+            asset_names(release.assets.iter())
+        ),
+        _ => bail!(
+            "asset に一致する添付ファイルが複数あります: {} ({} {})\n  一致したもの: {}",
+            source.asset,
+            source.github,
+            release.tag_name,
+            asset_names(matched.into_iter())
+        ),
+    }
+}
+
+fn asset_names<'a>(assets: impl Iterator<Item = &'a Asset>) -> String {
+    assets
+        .map(|asset| asset.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// API へのリクエストに付けるヘッダー。`GITHUB_TOKEN` は認証情報ファイルの設定より優先します。
+fn api_headers(url: &str) -> Result<Vec<(String, String)>> {
+    let mut headers = request_headers(url, None)?;
+    if let Some(token) = github_token() {
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Authorization"));
+        headers.push(("Authorization".to_string(), format!("Bearer {token}")));
+    }
+    headers.push(("X-GitHub-Api-Version".to_string(), "2022-11-28".to_string()));
+    Ok(headers)
+}
+
+fn github_token() -> Option<String> {
+    std::env::var(TOKEN_ENV)
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// `AU2_GITHUB_API_BASE` で GitHub Enterprise などの API を指定できます。
+fn api_base() -> String {
+    std::env::var(API_BASE_ENV)
+        .ok()
+        .map(|base| base.trim().trim_end_matches('/').to_string())
+        .filter(|base| !base.is_empty())
+        .unwrap_or_else(|| DEFAULT_API_BASE.to_string())
+}
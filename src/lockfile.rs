@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const LOCKFILE_NAME: &str = "aviutl2.lock";
+const HEADER: &str = "# このファイルは au2 が自動生成します。手動で編集しないでください。\n";
+
+/// 解決したリビジョンを記録するファイル（`aviutl2.lock`）
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub git: Vec<LockedGit>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedGit {
+    pub url: String,
+    pub rev: String,
+    pub commit: String,
+}
+
+impl Lockfile {
+    /// `root` のプロジェクトの `aviutl2.lock` を読み込みます。
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = lockfile_path(root)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content).with_context(|| format!("{} の解析に失敗しました", path.display()))
+    }
+
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let path = lockfile_path(root)?;
+        fs::write(&path, format!("{HEADER}{}", toml::to_string(self)?))?;
+        Ok(())
+    }
+
+    pub fn git_commit(&self, url: &str, rev: &str) -> Option<&str> {
+        self.git
+            .iter()
+            .find(|locked| locked.url == url && locked.rev == rev)
+            .map(|locked| locked.commit.as_str())
+    }
+
+    pub fn set_git_commit(&mut self, url: &str, rev: &str, commit: &str) {
+        match self
+            .git
+            .iter_mut()
+            .find(|locked| locked.url == url && locked.rev == rev)
+        {
+            Some(locked) => locked.commit = commit.to_string(),
+            None => self.git.push(LockedGit {
+                url: url.to_string(),
+                rev: rev.to_string(),
+                commit: commit.to_string(),
+            }),
+        }
+        self.git
+            .sort_by(|a, b| (&a.url, &a.rev).cmp(&(&b.url, &b.rev)));
+    }
+}
+
+fn lockfile_path(root: &Path) -> Result<PathBuf> {
+    Ok(std::path::absolute(root.join(LOCKFILE_NAME))?)
+}
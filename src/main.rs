@@ -1,9 +1,19 @@
+mod cache;
+mod cargo;
 mod catalog_schema;
 mod cli;
 mod commands;
 mod config;
+mod credentials;
+mod download;
+mod git;
+mod github;
+mod lockfile;
+mod pe;
 mod schema;
+mod template;
 mod util;
+mod version;
 
 use clap::Parser;
 
@@ -13,7 +23,7 @@ fn main() {
         .parse_default_env()
         .init();
     let cli = cli::Cli::parse();
-    if let Err(e) = commands::run(cli.command) {
+    if let Err(e) = commands::run(cli) {
         log::error!("{:?}", e);
         std::process::exit(1);
     }
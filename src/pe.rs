@@ -0,0 +1,188 @@
+use anyhow::{Context, Result, bail};
+use fs_err as fs;
+use std::path::Path;
+
+const MACHINE_AMD64: u16 = 0x8664;
+const MACHINE_I386: u16 = 0x014c;
+const MACHINE_ARM64: u16 = 0xaa64;
+const IMAGE_FILE_DLL: u16 = 0x2000;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+/// PE ファイルから読み取った情報
+pub struct PeInfo {
+    pub machine: u16,
+    pub is_dll: bool,
+    pub exports: Vec<String>,
+}
+
+impl PeInfo {
+    pub fn machine_name(&self) -> String {
+        match self.machine {
+            MACHINE_AMD64 => "x64".to_string(),
+            MACHINE_I386 => "x86".to_string(),
+            MACHINE_ARM64 => "ARM64".to_string(),
+            machine => format!("0x{machine:04x}"),
+        }
+    }
+}
+
+/// PE ファイルのヘッダーとエクスポートテーブルを読み取ります。
+pub fn inspect(path: &Path) -> Result<PeInfo> {
+    let data = fs::read(path)?;
+    parse(&data).with_context(|| format!("有効な PE ファイルではありません: {}", path.display()))
+}
+
+fn parse(data: &[u8]) -> Result<PeInfo> {
+    if data.get(0..2) != Some(b"MZ") {
+        bail!("MZ ヘッダーがありません");
+    }
+    let pe_offset = read_u32(data, 0x3c)? as usize;
+    if data.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0") {
+        bail!("PE シグネチャがありません");
+    }
+    let coff = pe_offset + 4;
+    let machine = read_u16(data, coff)?;
+    let section_count = read_u16(data, coff + 2)? as usize;
+    let optional_size = read_u16(data, coff + 16)? as usize;
+    let characteristics = read_u16(data, coff + 18)?;
+    let optional = coff + 20;
+    let magic = read_u16(data, optional)?;
+    // データディレクトリの位置は PE32 と PE32+ で異なる
+    let directories = if magic == PE32_PLUS_MAGIC {
+        optional + 112
+    } else {
+        optional + 96
+    };
+    let directory_count = read_u32(data, directories - 4)?;
+
+    let sections = (0..section_count)
+        .map(|i| {
+            let header = optional + optional_size + i * 40;
+            Ok(Section {
+                virtual_size: read_u32(data, header + 8)?,
+                virtual_address: read_u32(data, header + 12)?,
+                raw_size: read_u32(data, header + 16)?,
+                raw_offset: read_u32(data, header + 20)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut exports = Vec::new();
+    let export_rva = if directory_count > 0 {
+        read_u32(data, directories)?
+    } else {
+        0
+    };
+    if export_rva != 0 {
+        let export = rva_to_offset(&sections, export_rva)?;
+        let name_count = read_u32(data, export + 24)? as usize;
+        // 名前で公開している関数がなければ、名前の表の位置は使われない
+        let names = if name_count > 0 {
+            rva_to_offset(&sections, read_u32(data, export + 32)?)?
+        } else {
+            0
+        };
+        for i in 0..name_count {
+            let name = rva_to_offset(&sections, read_u32(data, names + i * 4)?)?;
+            exports.push(read_c_string(data, name)?);
+        }
+    }
+    Ok(PeInfo {
+        machine,
+        is_dll: characteristics & IMAGE_FILE_DLL != 0,
+        exports,
+    })
+}
+
+struct Section {
+    virtual_size: u32,
+    virtual_address: u32,
+    raw_size: u32,
+    raw_offset: u32,
+}
+
+fn rva_to_offset(sections: &[Section], rva: u32) -> Result<usize> {
+    let section = sections
+        .iter()
+        .find(|section| {
+            let size = section.virtual_size.max(section.raw_size);
+            rva >= section.virtual_address && rva < section.virtual_address.saturating_add(size)
+        })
+        .with_context(|| format!("RVA 0x{rva:08x} がどのセクションにも含まれません"))?;
+    let offset = (rva - section.virtual_address)
+        .checked_add(section.raw_offset)
+        .with_context(|| format!("RVA 0x{rva:08x} のファイル上の位置が不正です"))?;
+    Ok(offset as usize)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("ファイルが途中で終わっています")?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("ファイルが途中で終わっています")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_c_string(data: &[u8], offset: usize) -> Result<String> {
+    let rest = data
+        .get(offset..)
+        .context("ファイルが途中で終わっています")?;
+    let end = rest
+        .iter()
+        .position(|byte| *byte == 0)
+        .context("文字列が終端されていません")?;
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+/// 配置先の拡張子がプラグインのものなら、公開しているべき関数名を返します。
+/// 関数名を確認しない種類は `Some(None)` です。
+fn expected_export(destination: &Path) -> Option<Option<&'static str>> {
+    let extension = destination.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "aui2" => Some(Some("GetInputPluginTable")),
+        "auo2" => Some(Some("GetOutputPluginTable")),
+        "auf2" => Some(Some("GetFilterPluginTable")),
+        "mod2" => Some(Some("GetScriptModuleTable")),
+        // 汎用プラグインの関数名は実際のプラグインで確かめられていないので、x64 の DLL であることだけを確認する
+        "aux2" => Some(None),
+        _ => None,
+    }
+}
+
+/// プラグインとして配置するファイルが x64 の DLL で、種類に応じた関数を公開しているか確認します。
+/// プラグインの拡張子でない場合やディレクトリの場合は何もしません。
+pub fn verify_plugin(source: &Path, destination: &Path) -> Result<()> {
+    let Some(export) = expected_export(destination) else {
+        return Ok(());
+    };
+    if !source.is_file() {
+        return Ok(());
+    }
+    let info = inspect(source)?;
+    if info.machine != MACHINE_AMD64 {
+        bail!(
+            "プラグインが x64 向けにビルドされていません（{}）: {}",
+            info.machine_name(),
+            source.display()
+        );
+    }
+    if !info.is_dll {
+        bail!("プラグインが DLL ではありません: {}", source.display());
+    }
+    if let Some(export) = export
+        && !info.exports.iter().any(|name| name == export)
+    {
+        bail!(
+            "プラグインが {} を公開していません: {}",
+            export,
+            source.display()
+        );
+    }
+    Ok(())
+}
@@ -1 +1,257 @@
+use serde_json::Value;
+
 pub const CONFIG_SCHEMA_JSON: &str = include_str!("./schema.json");
+
+/// 設定ファイル内のキーのパスの要素
+#[derive(Clone)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// スキーマに定義されていないキー
+pub struct UnknownKey {
+    /// キー自身を含むパス
+    pub path: Vec<Segment>,
+    /// 綴りが近い正しいキー
+    pub suggestion: Option<String>,
+}
+
+impl UnknownKey {
+    pub fn message(&self) -> String {
+        match &self.suggestion {
+            Some(suggestion) => {
+                format!("不明なキーです（{} の誤りではありませんか？）", suggestion)
+            }
+            None => "不明なキーです".to_string(),
+        }
+    }
+}
+
+/// `value` のうち、スキーマに定義されていないキーを探します。
+pub fn unknown_keys(value: &Value) -> Vec<UnknownKey> {
+    let root: Value =
+        serde_json::from_str(CONFIG_SCHEMA_JSON).expect("埋め込みの JSON Schema が不正です");
+    let mut found = Vec::new();
+    walk(&root, &root, value, &mut Vec::new(), &mut found);
+    found
+}
+
+fn walk(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &mut Vec<Segment>,
+    found: &mut Vec<UnknownKey>,
+) {
+    let schema = resolve(root, schema);
+    let branches = schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Value::as_array);
+    if let Some(branches) = branches {
+        let branches = branches
+            .iter()
+            .map(|branch| resolve(root, branch))
+            .collect::<Vec<_>>();
+        match value {
+            Value::Object(table) => {
+                let objects = branches
+                    .into_iter()
+                    .filter(|branch| branch.get("properties").is_some())
+                    .collect::<Vec<_>>();
+                // 必須のキーと const が一致する候補が一つに絞れればそれで確認する
+                let matched = objects
+                    .iter()
+                    .filter(|branch| branch_matches(branch, table))
+                    .collect::<Vec<_>>();
+                if let [branch] = matched.as_slice() {
+                    walk(root, branch, value, path, found);
+                } else {
+                    walk_union(root, &objects, table, path, found);
+                }
+            }
+            Value::Array(_) => {
+                if let Some(branch) = branches
+                    .into_iter()
+                    .find(|branch| branch.get("items").is_some())
+                {
+                    walk(root, branch, value, path, found);
+                }
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    match value {
+        Value::Object(table) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional = schema
+                .get("additionalProperties")
+                .filter(|additional| additional.is_object());
+            if properties.is_none() && additional.is_none() {
+                return;
+            }
+            for (key, child) in table {
+                path.push(Segment::Key(key.clone()));
+                match properties
+                    .and_then(|properties| properties.get(key))
+                    .or(additional)
+                {
+                    Some(child_schema) => walk(root, child_schema, child, path, found),
+                    None => found.push(unknown_key(
+                        path,
+                        properties
+                            .into_iter()
+                            .flat_map(|properties| properties.keys()),
+                    )),
+                }
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    path.push(Segment::Index(index));
+                    walk(root, item_schema, item, path, found);
+                    path.pop();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// どの形式か判断できない場合は、いずれかの候補にあるキーを正しいものとして扱います。
+fn walk_union(
+    root: &Value,
+    branches: &[&Value],
+    table: &serde_json::Map<String, Value>,
+    path: &mut Vec<Segment>,
+    found: &mut Vec<UnknownKey>,
+) {
+    if branches.is_empty() {
+        return;
+    }
+    let known = || {
+        branches
+            .iter()
+            .filter_map(|branch| branch.get("properties").and_then(Value::as_object))
+            .flat_map(|properties| properties.keys())
+    };
+    for (key, child) in table {
+        path.push(Segment::Key(key.clone()));
+        let child_schema = branches
+            .iter()
+            .find_map(|branch| branch.get("properties").and_then(|p| p.get(key)));
+        match child_schema {
+            Some(child_schema) => walk(root, child_schema, child, path, found),
+            None => found.push(unknown_key(path, known())),
+        }
+        path.pop();
+    }
+}
+
+fn branch_matches(branch: &Value, table: &serde_json::Map<String, Value>) -> bool {
+    let required = branch
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .all(|key| table.contains_key(key));
+    let constants = branch
+        .get("properties")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .all(
+            |(key, property)| match (property.get("const"), table.get(key)) {
+                (Some(expected), Some(actual)) => expected == actual,
+                _ => true,
+            },
+        );
+    required && constants
+}
+
+fn resolve<'a>(root: &'a Value, mut schema: &'a Value) -> &'a Value {
+    while let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let Some(resolved) = reference
+            .strip_prefix("#/")
+            .and_then(|pointer| root.pointer(&format!("/{pointer}")))
+        else {
+            break;
+        };
+        schema = resolved;
+    }
+    schema
+}
+
+fn unknown_key<'a>(path: &[Segment], known: impl Iterator<Item = &'a String>) -> UnknownKey {
+    let path = path.to_vec();
+    let Some(Segment::Key(key)) = path.last() else {
+        unreachable!("キーのパスは空になりません");
+    };
+    let threshold = (key.chars().count() / 3).max(1);
+    let suggestion = known
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone());
+    UnknownKey { path, suggestion }
+}
+
+/// 隣り合う文字の入れ替えも 1 と数える編集距離
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// `artifacts.my_plugin.build[0]` のような表示用の文字列にします。
+pub fn key_path(path: &[Segment]) -> String {
+    if path.is_empty() {
+        return "(ルート)".to_string();
+    }
+    let mut result = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) => {
+                if !result.is_empty() {
+                    result.push('.');
+                }
+                if !key.is_empty()
+                    && key
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                {
+                    result.push_str(key);
+                } else {
+                    result.push_str(&format!("{key:?}"));
+                }
+            }
+            Segment::Index(index) => result.push_str(&format!("[{index}]")),
+        }
+    }
+    result
+}
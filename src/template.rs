@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::config::Project;
+
+/// `{name}` などに入れる値。`None` のものは展開せずにそのまま残します。
+#[derive(Default, Clone, Copy)]
+pub struct TemplateContext<'a> {
+    pub name: Option<&'a str>,
+    pub version: Option<&'a str>,
+    pub profile: Option<&'a str>,
+}
+
+impl<'a> TemplateContext<'a> {
+    pub fn project(project: &'a Project) -> Self {
+        Self {
+            name: Some(&project.name),
+            version: Some(&project.version),
+            profile: None,
+        }
+    }
+
+    pub fn with_profile(self, profile: Option<&'a str>) -> Self {
+        Self { profile, ..self }
+    }
+}
+
+/// `${env:NAME}`、`${env:NAME:-default}`、`{name}`、`{version}`、`{profile}` を展開します。
+/// 設定されていない環境変数はエラーです。それ以外の `{...}` はシェルのコマンドなどで使われるのでそのまま残します。
+pub fn expand(input: &str, context: &TemplateContext) -> Result<String> {
+    let placeholders = [
+        ("{name}", context.name),
+        ("{version}", context.version),
+        ("{profile}", context.profile),
+    ];
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find(['$', '{']) {
+        output.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(after) = tail.strip_prefix("${env:") {
+            let end = after
+                .find('}')
+                .with_context(|| format!("`${{env:` が閉じられていません: {input}"))?;
+            let expression = &after[..end];
+            let value = match expression.split_once(":-") {
+                Some((name, default)) => std::env::var(name)
+                    .ok()
+                    .filter(|value| !value.is_empty())
+                    .unwrap_or_else(|| default.to_string()),
+                None => std::env::var(expression)
+                    .with_context(|| format!("環境変数 {expression} が設定されていません"))?,
+            };
+            output.push_str(&value);
+            rest = &after[end + 1..];
+        } else if let Some((placeholder, Some(value))) = placeholders
+            .iter()
+            .find(|(placeholder, value)| value.is_some() && tail.starts_with(placeholder))
+        {
+            output.push_str(value);
+            rest = &tail[placeholder.len()..];
+        } else {
+            // `$` と `{` はどちらも 1 バイト
+            output.push_str(&tail[..1]);
+            rest = &tail[1..];
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// 環境変数だけを展開します。
+pub fn expand_env(input: &str) -> Result<String> {
+    expand(input, &TemplateContext::default())
+}
+
+/// 読み込んだ設定ファイルの `key` の値に含まれる環境変数を展開します。
+pub fn expand_config(table: &mut toml::Table, key: &str) -> Result<()> {
+    if let Some(value) = table.get_mut(key) {
+        expand_value(
+            value,
+            &TemplateContext::default(),
+            &mut vec![key.to_string()],
+        )?;
+    }
+    Ok(())
+}
+
+/// 読み込んだ後の設定の値に含まれる文字列をすべて展開します。
+pub fn expand_all<T: Serialize + DeserializeOwned>(
+    value: &T,
+    context: &TemplateContext,
+) -> Result<T> {
+    let mut value = toml::Value::try_from(value)?;
+    expand_value(&mut value, context, &mut Vec::new())?;
+    Ok(value.try_into()?)
+}
+
+fn expand_value(
+    value: &mut toml::Value,
+    context: &TemplateContext,
+    path: &mut Vec<String>,
+) -> Result<()> {
+    match value {
+        toml::Value::String(string) => {
+            let expanded = expand(string, context);
+            *string = if path.is_empty() {
+                expanded?
+            } else {
+                expanded.with_context(|| format!("{} の展開に失敗しました", path.join(".")))?
+            };
+        }
+        toml::Value::Array(array) => {
+            for (index, item) in array.iter_mut().enumerate() {
+                path.push(index.to_string());
+                expand_value(item, context, path)?;
+                path.pop();
+            }
+        }
+        toml::Value::Table(table) => {
+            // ヘッダーは秘密情報を含みうるので、リクエストを作る時点で展開する（credentials::request_headers）
+            for (key, value) in table.iter_mut().filter(|(key, _)| *key != "headers") {
+                path.push(key.clone());
+                expand_value(value, context, path)?;
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -1,14 +1,16 @@
 use anyhow::{Context, Result, bail};
 use fs_err as fs;
 use fs_err::File;
-use std::hash::{Hash, Hasher};
+use sha2::{Digest, Sha256};
 use std::io::Read;
 use std::io::Write;
 use std::path::{Component, Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
 use walkdir::WalkDir;
+use xxhash_rust::xxh3::Xxh3;
 use zip::write::FileOptions;
 
+use crate::config::{ArtifactSource, CachePolicy};
+
 pub fn safe_join(base: &Path, entry_name: &str) -> Result<PathBuf> {
     let mut normalized = PathBuf::new();
     for component in Path::new(entry_name).components() {
@@ -25,6 +27,8 @@ pub fn extract_zip(zip_path: &Path, dest_dir: &Path) -> Result<()> {
     let file = File::open(zip_path)
         .with_context(|| format!("zip の読み込みに失敗しました: {}", zip_path.display()))?;
     let mut archive = zip::ZipArchive::new(file).context("zip の解析に失敗しました")?;
+    verify_zip(&mut archive)
+        .with_context(|| format!("zip が破損しています: {}", zip_path.display()))?;
 
     for i in 0..archive.len() {
         let mut entry = archive.by_index(i)?;
@@ -49,6 +53,73 @@ pub fn extract_zip(zip_path: &Path, dest_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// zip 内のファイルのうち `pattern`（glob）に一致するもののエントリ名を返します。
+pub fn zip_entries_matching(zip_path: &Path, pattern: &str) -> Result<Vec<String>> {
+    let matcher = globset::GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("path のパターンが不正です: {pattern}"))?
+        .compile_matcher();
+    let file = File::open(zip_path)
+        .with_context(|| format!("zip の読み込みに失敗しました: {}", zip_path.display()))?;
+    let archive = zip::ZipArchive::new(file).context("zip の解析に失敗しました")?;
+    let mut names = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/') && matcher.is_match(name))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+/// zip のうち `names` のファイルだけを `dest_dir` に展開します。
+pub fn extract_zip_entries(zip_path: &Path, dest_dir: &Path, names: &[String]) -> Result<()> {
+    let file = File::open(zip_path)
+        .with_context(|| format!("zip の読み込みに失敗しました: {}", zip_path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).context("zip の解析に失敗しました")?;
+    verify_zip_entries(&mut archive, names)
+        .with_context(|| format!("zip が破損しています: {}", zip_path.display()))?;
+    for name in names {
+        let mut entry = archive.by_name(name)?;
+        let out_path = safe_join(dest_dir, name)?;
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        remove_path(&out_path)?;
+        let mut out_file = File::create(&out_path)?;
+        std::io::copy(&mut entry, &mut out_file)
+            .with_context(|| format!("zip の展開に失敗しました: {name}"))?;
+    }
+    Ok(())
+}
+
+/// 展開前にすべてのエントリを読み、CRC が一致することを確認します。
+fn verify_zip<R: Read + std::io::Seek>(archive: &mut zip::ZipArchive<R>) -> Result<()> {
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        std::io::copy(&mut entry, &mut std::io::sink())
+            .with_context(|| format!("CRC の検証に失敗しました: {name}"))?;
+    }
+    Ok(())
+}
+
+/// 展開前に `names` のエントリを読み、CRC が一致することを確認します。
+fn verify_zip_entries<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    names: &[String],
+) -> Result<()> {
+    for name in names {
+        let mut entry = archive.by_name(name)?;
+        std::io::copy(&mut entry, &mut std::io::sink())
+            .with_context(|| format!("CRC の検証に失敗しました: {name}"))?;
+    }
+    Ok(())
+}
+
 pub fn create_zip(source_dir: &Path, zip_path: &Path) -> Result<()> {
     let file = File::create(zip_path)?;
     let mut zip = zip::ZipWriter::new(file);
@@ -235,14 +306,10 @@ fn path_to_slash(path: &Path) -> String {
     parts.join("/")
 }
 
-pub fn fill_template(template: &str, project: &crate::config::Project) -> String {
-    template
-        .replace("{name}", &project.name)
-        .replace("{version}", &project.version)
-}
-
-pub fn development_dir(dev: &crate::config::Development) -> Result<PathBuf> {
-    if let Some(install_dir) = dev.install_dir.as_deref() {
+pub fn development_dir(dev: Option<&crate::config::Development>) -> Result<PathBuf> {
+    if let Some(install_dir) = dev.and_then(|dev| dev.install_dir.as_deref()) {
+        let install_dir = crate::template::expand_env(install_dir)
+            .context("development.install_dir の展開に失敗しました")?;
         return Ok(PathBuf::from(install_dir));
     }
     let mut base = cli_dir()?;
@@ -250,8 +317,10 @@ pub fn development_dir(dev: &crate::config::Development) -> Result<PathBuf> {
     Ok(base)
 }
 
-pub fn preview_dir(preview: &crate::config::Preview) -> Result<PathBuf> {
-    if let Some(install_dir) = preview.install_dir.as_deref() {
+pub fn preview_dir(preview: Option<&crate::config::Preview>) -> Result<PathBuf> {
+    if let Some(install_dir) = preview.and_then(|preview| preview.install_dir.as_deref()) {
+        let install_dir = crate::template::expand_env(install_dir)
+            .context("preview.install_dir の展開に失敗しました")?;
         return Ok(PathBuf::from(install_dir));
     }
     let mut base = cli_dir()?;
@@ -259,83 +328,194 @@ pub fn preview_dir(preview: &crate::config::Preview) -> Result<PathBuf> {
     Ok(base)
 }
 
-pub fn resolve_source(source: &str, refresh: bool) -> Result<PathBuf> {
-    if is_http_url(source) {
-        let downloaded = download_http_source(source, refresh)?;
-        return Ok(downloaded);
+/// 解決した source のファイル
+pub struct ResolvedSource {
+    pub path: PathBuf,
+    /// glob で複数のファイルを選んだ場合の、destination からの相対パス
+    pub subpath: Option<PathBuf>,
+}
+
+impl ResolvedSource {
+    fn file(path: PathBuf) -> Self {
+        Self {
+            path,
+            subpath: None,
+        }
     }
-    Ok(PathBuf::from(source))
 }
 
-fn is_http_url(source: &str) -> bool {
-    source.starts_with("http://") || source.starts_with("https://")
+/// `root` は source を書いたプロジェクトのディレクトリです。git の source はそのプロジェクトの
+/// `.aviutl2-cli` と `aviutl2.lock` を使います。
+pub fn resolve_source(
+    source: &ArtifactSource,
+    root: &Path,
+    refresh: bool,
+) -> Result<Vec<ResolvedSource>> {
+    match source {
+        ArtifactSource::Path(source) if is_http_url(source) => {
+            let headers = crate::credentials::request_headers(source, None)?;
+            let path = crate::cache::fetch(
+                source,
+                &headers,
+                &Checksum::default(),
+                CachePolicy::Forever,
+                refresh,
+            )?;
+            Ok(vec![ResolvedSource::file(path)])
+        }
+        ArtifactSource::Path(source) => Ok(vec![ResolvedSource::file(PathBuf::from(source))]),
+        ArtifactSource::Url(source) => {
+            if !is_http_url(&source.url) {
+                bail!(
+                    "source.url は http/https の URL である必要があります: {}",
+                    source.url
+                );
+            }
+            let checksum = Checksum {
+                sha256: source.sha256.as_deref(),
+                xxh3: source.xxh3.as_deref(),
+            };
+            let policy = source.cache.unwrap_or(CachePolicy::Forever);
+            let headers =
+                crate::credentials::request_headers(&source.url, source.headers.as_ref())?;
+            let downloaded =
+                crate::cache::fetch(&source.url, &headers, &checksum, policy, refresh)?;
+            select_from_archive(downloaded, source.path.as_deref(), &source.url)
+        }
+        ArtifactSource::GitHub(source) => {
+            let (url, headers) = crate::github::resolve_asset(source, refresh)?;
+            let checksum = Checksum {
+                sha256: source.sha256.as_deref(),
+                xxh3: source.xxh3.as_deref(),
+            };
+            let policy = source.cache.unwrap_or(CachePolicy::Forever);
+            let downloaded = crate::cache::fetch(&url, &headers, &checksum, policy, refresh)?;
+            select_from_archive(downloaded, source.path.as_deref(), &url)
+        }
+        ArtifactSource::Git(source) => Ok(vec![ResolvedSource::file(crate::git::resolve(
+            source, root, refresh,
+        )?)]),
+        ArtifactSource::Project(source) => bail!(
+            "project の source はここでは解決できません: {}",
+            source.project
+        ),
+        ArtifactSource::Cargo(source) => {
+            bail!("cargo の source はここでは解決できません: {}", source.cargo)
+        }
+    }
 }
 
-fn download_http_source(url: &str, refresh: bool) -> Result<PathBuf> {
-    let file_name = filename_from_url(url);
-    let cache_dir = http_cache_dir()?;
-    fs::create_dir_all(&cache_dir)?;
-    let hash = hash_url(url);
-    let cache_path = cache_dir.join(format!("{hash}_{file_name}"));
-    if cache_path.exists() && !refresh {
-        log::info!(
-            "source のキャッシュを使用します: {} -> {}",
-            url,
-            cache_path.display()
-        );
-        return Ok(cache_path);
+/// `path` が指定されていれば、ダウンロードした zip からファイルを取り出します。
+fn select_from_archive(
+    downloaded: PathBuf,
+    pattern: Option<&str>,
+    url: &str,
+) -> Result<Vec<ResolvedSource>> {
+    let Some(pattern) = pattern else {
+        return Ok(vec![ResolvedSource::file(downloaded)]);
+    };
+    let (extract_dir, names) = crate::cache::extract(&downloaded, pattern)
+        .with_context(|| format!("zip からの取り出しに失敗しました: {url}"))?;
+    if !is_glob(pattern) {
+        return Ok(vec![ResolvedSource::file(safe_join(
+            &extract_dir,
+            &names[0],
+        )?)]);
     }
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_nanos();
-    let mut temp_path = cache_dir.join(format!("{ts}_{file_name}.partial"));
-
-    let agent: ureq::Agent = ureq::Agent::config_builder()
-        .max_redirects(5)
-        .build()
-        .into();
-    let response = agent
-        .get(url)
-        .header("User-Agent", "aviutl2-cli")
-        .call()
-        .with_context(|| format!("source のダウンロードに失敗しました: {url}"))?;
-    let status = response.status();
-    if !status.is_success() {
-        bail!("source のダウンロードに失敗しました: {} ({})", url, status);
+    let prefix = glob_literal_prefix(pattern);
+    names
+        .iter()
+        .map(|name| {
+            Ok(ResolvedSource {
+                path: safe_join(&extract_dir, name)?,
+                subpath: Some(PathBuf::from(name.strip_prefix(&prefix).unwrap_or(name))),
+            })
+        })
+        .collect()
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// glob のうちワイルドカードを含まない先頭のディレクトリ部分（`x64/*.aux2` なら `x64/`）。
+fn glob_literal_prefix(pattern: &str) -> String {
+    let mut prefix = String::new();
+    let mut parts = pattern.split('/').peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() || is_glob(part) {
+            break;
+        }
+        prefix.push_str(part);
+        prefix.push('/');
     }
+    prefix
+}
 
-    let (_parts, body) = response.into_parts();
-    let mut reader = body.into_reader();
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf)?;
+/// 期待するハッシュ値。指定されたものだけを検証します。
+#[derive(Default, Clone, Copy)]
+pub struct Checksum<'a> {
+    pub sha256: Option<&'a str>,
+    pub xxh3: Option<&'a str>,
+}
 
-    if temp_path.exists() {
-        temp_path = cache_dir.join(format!("{ts}_{}_1.partial", file_name));
+impl Checksum<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.sha256.is_none() && self.xxh3.is_none()
     }
-    let mut file = File::create(&temp_path)?;
-    file.write_all(&buf)?;
-    if cache_path.exists() {
-        remove_path(&cache_path)?;
+}
+
+/// ファイルのハッシュ値が `checksum` と一致するか検証します。
+pub fn verify_checksum(path: &Path, checksum: &Checksum) -> Result<()> {
+    if checksum.is_empty() {
+        return Ok(());
     }
-    fs::rename(&temp_path, &cache_path)?;
-    log::info!(
-        "source をダウンロードしました: {} -> {}",
-        url,
-        cache_path.display()
-    );
-    Ok(cache_path)
+    let mut file = File::open(path)?;
+    let mut sha256 = Sha256::new();
+    let mut xxh3 = Xxh3::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        if checksum.sha256.is_some() {
+            sha256.update(&buf[..read]);
+        }
+        if checksum.xxh3.is_some() {
+            xxh3.update(&buf[..read]);
+        }
+    }
+    if let Some(expected) = checksum.sha256 {
+        let actual = to_hex(&sha256.finalize());
+        ensure_checksum_matches(path, "sha256", expected, &actual)?;
+    }
+    if let Some(expected) = checksum.xxh3 {
+        let actual = format!("{:032x}", xxh3.digest128());
+        ensure_checksum_matches(path, "xxh3", expected, &actual)?;
+    }
+    Ok(())
 }
 
-fn filename_from_url(url: &str) -> String {
-    let url = url.split('#').next().unwrap_or(url);
-    let url = url.split('?').next().unwrap_or(url);
-    let name = url.rsplit('/').next().unwrap_or("download");
-    if name.is_empty() {
-        "download".to_string()
-    } else {
-        name.to_string()
+fn ensure_checksum_matches(path: &Path, kind: &str, expected: &str, actual: &str) -> Result<()> {
+    if !expected.trim().eq_ignore_ascii_case(actual) {
+        bail!(
+            "{} が一致しません: {}\n  期待値: {}\n  実際の値: {}",
+            kind,
+            path.display(),
+            expected.trim(),
+            actual
+        );
     }
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn is_http_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
 }
 
 pub fn release_stage_dir() -> Result<PathBuf> {
@@ -344,26 +524,31 @@ pub fn release_stage_dir() -> Result<PathBuf> {
     Ok(base)
 }
 
-fn http_cache_dir() -> Result<PathBuf> {
+pub fn cache_dir() -> Result<PathBuf> {
     let mut base = cli_dir()?;
     base.push("cache");
     Ok(base)
 }
 
+pub fn aviutl2_cache_dir() -> Result<PathBuf> {
+    let mut base = cache_dir()?;
+    base.push("aviutl2");
+    Ok(base)
+}
+
 fn cli_dir() -> Result<PathBuf> {
     let mut base = std::env::current_dir().context("カレントディレクトリの取得に失敗しました")?;
     base.push(".aviutl2-cli");
     Ok(base)
 }
 
+/// `root` のプロジェクトの git の source を置くディレクトリ
+pub fn git_dir(root: &Path) -> Result<PathBuf> {
+    Ok(std::path::absolute(root.join(".aviutl2-cli").join("git"))?)
+}
+
 pub fn prepare_snapshot_path() -> Result<PathBuf> {
     let mut base = cli_dir()?;
     base.push("prepare-artifacts.json");
     Ok(base)
 }
-
-fn hash_url(url: &str) -> String {
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    url.hash(&mut hasher);
-    format!("{:016x}", hasher.finish())
-}
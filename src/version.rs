@@ -0,0 +1,98 @@
+use anyhow::{Context, Result, bail};
+use fs_err as fs;
+use std::path::Path;
+
+use crate::config::{VersionOrigin, VersionSource};
+
+/// `project.version` の取得元からバージョンを読み取ります。`root` はプロジェクトのディレクトリです。
+pub fn resolve(source: &VersionSource, root: &Path) -> Result<String> {
+    let (version, prefix) = match source {
+        VersionSource::From(from) => {
+            let version = match from.from {
+                VersionOrigin::CargoToml => from_cargo_toml(root)?,
+                VersionOrigin::PackageJson => from_package_json(root)?,
+                VersionOrigin::GitTag => crate::git::latest_tag(root, from.prefix.as_deref())?,
+            };
+            (version, from.prefix.as_deref())
+        }
+        VersionSource::File(file) => {
+            let path = root.join(&file.file);
+            let version = fs::read_to_string(&path)?.trim().to_string();
+            if version.is_empty() {
+                bail!("バージョンのファイルが空です: {}", path.display());
+            }
+            (version, None)
+        }
+    };
+    let version = match prefix {
+        Some(prefix) => version
+            .strip_prefix(prefix)
+            .map(str::to_string)
+            .unwrap_or(version),
+        None => version,
+    };
+    log::debug!("project.version を解決しました: {}", version);
+    Ok(version)
+}
+
+fn from_cargo_toml(root: &Path) -> Result<String> {
+    let path = root.join("Cargo.toml");
+    let manifest: toml::Table = toml::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Cargo.toml の解析に失敗しました: {}", path.display()))?;
+    let version = manifest
+        .get("package")
+        .and_then(|package| package.get("version"))
+        .with_context(|| {
+            format!(
+                "Cargo.toml に package.version がありません: {}",
+                path.display()
+            )
+        })?;
+    if let Some(version) = version.as_str() {
+        return Ok(version.to_string());
+    }
+    if version.get("workspace").and_then(toml::Value::as_bool) != Some(true) {
+        bail!(
+            "Cargo.toml の package.version が不正です: {}",
+            path.display()
+        );
+    }
+    // `version.workspace = true` の場合はワークスペースの設定を探す
+    for dir in fs::canonicalize(root)?.ancestors() {
+        let path = dir.join("Cargo.toml");
+        if !path.is_file() {
+            continue;
+        }
+        let manifest: toml::Table = toml::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Cargo.toml の解析に失敗しました: {}", path.display()))?;
+        let Some(workspace) = manifest.get("workspace") else {
+            continue;
+        };
+        return workspace
+            .get("package")
+            .and_then(|package| package.get("version"))
+            .and_then(toml::Value::as_str)
+            .map(str::to_string)
+            .with_context(|| {
+                format!(
+                    "Cargo.toml に workspace.package.version がありません: {}",
+                    path.display()
+                )
+            });
+    }
+    bail!(
+        "ワークスペースの Cargo.toml が見つかりません: {}",
+        root.display()
+    );
+}
+
+fn from_package_json(root: &Path) -> Result<String> {
+    let path = root.join("package.json");
+    let package: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("package.json の解析に失敗しました: {}", path.display()))?;
+    package
+        .get("version")
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string)
+        .with_context(|| format!("package.json に version がありません: {}", path.display()))
+}
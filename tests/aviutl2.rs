@@ -0,0 +1,186 @@
+mod common;
+
+use assert_cmd::Command;
+use fs_err as fs;
+use predicates::prelude::*;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+fn setup_project(project_dir: &Path) -> Result<(), std::io::Error> {
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"versions\"\nversion = \"0.1.0\"\n\n[artifacts]\n\n[development]\naviutl2_version = \"2.0beta2\"\ninstall_dir = \"dev\"\n",
+    )?;
+    write_file(
+        &project_dir.join("dev").join(".aviutl2-version"),
+        b"2.0beta2",
+    )?;
+    write_file(
+        &project_dir
+            .join(".aviutl2-cli")
+            .join("cache")
+            .join("aviutl2")
+            .join("aviutl2-2.0beta1.zip"),
+        b"zip",
+    )
+}
+
+#[test]
+fn aviutl2_list_marks_installed_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("aviutl2_list_project");
+    setup_project(&project_dir)?;
+
+    let server = common::StubServer::start(|request| match request.path.as_str() {
+        "/versions" => common::StubResponse::ok(
+            r#"[
+                {"version": "2.0beta2", "date": "2025-02-01", "notes": "second"},
+                {"version": "2.0beta1", "date": "2025-01-01", "notes": "first"},
+                {"version": "2.0beta0"}
+            ]"#,
+        )
+        .header("Content-Type", "application/json"),
+        _ => common::StubResponse::status(404),
+    })?;
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_API_BASE", server.url(""))
+        .args(["aviutl2", "list", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(json[0]["version"], "2.0beta2");
+    assert_eq!(json[0]["date"], "2025-02-01");
+    assert_eq!(json[0]["installed"], serde_json::json!(["development"]));
+    assert_eq!(json[1]["installed"], serde_json::json!(["cache"]));
+    assert_eq!(json[2]["installed"], serde_json::json!([]));
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_API_BASE", server.url(""))
+        .args(["aviutl2", "list"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "2.0beta2  2025-02-01  [development]",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn aviutl2_installed_lists_local_installs() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("aviutl2_installed_project");
+    setup_project(&project_dir)?;
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["aviutl2", "installed", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(json.as_array().map(Vec::len), Some(2));
+    assert_eq!(json[0]["location"], "development");
+    assert_eq!(json[0]["version"], "2.0beta2");
+    assert_eq!(json[1]["location"], "cache");
+    assert_eq!(json[1]["version"], "2.0beta1");
+
+    Ok(())
+}
+
+#[test]
+fn aviutl2_installed_shows_local_source_instead_of_fingerprint()
+-> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("aviutl2_installed_local_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"versions\"\nversion = \"0.1.0\"\n\n[artifacts]\n\n[development]\naviutl2_version = { path = \"vendor/aviutl2.zip\" }\ninstall_dir = \"dev\"\n",
+    )?;
+    write_file(
+        &project_dir.join("dev").join(".aviutl2-version"),
+        b"path:vendor/aviutl2.zip:0123456789abcdef0123456789abcdef",
+    )?;
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["aviutl2", "installed", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(json[0]["location"], "development");
+    assert_eq!(json[0]["version"], "ローカル (vendor/aviutl2.zip)");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["aviutl2", "installed"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("ローカル (vendor/aviutl2.zip)"))
+        .stdout(predicates::str::contains("0123456789abcdef").not());
+
+    Ok(())
+}
+
+#[test]
+fn aviutl2_list_marks_latest_install_with_resolved_version()
+-> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("aviutl2_latest_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"versions\"\nversion = \"0.1.0\"\n\n[artifacts]\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+    )?;
+
+    let archive = common::zip_bytes(&[("aviutl2.exe", b"exe")]);
+    let server = common::StubServer::start(move |request| match request.path.as_str() {
+        "/versions" => {
+            common::StubResponse::ok(r#"[{"version": "2.0beta3"}, {"version": "2.0beta2"}]"#)
+        }
+        "/download" => common::StubResponse::ok(archive.clone()),
+        _ => common::StubResponse::status(404),
+    })?;
+    let au2 = |args: &[&str]| {
+        Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+            .current_dir(&project_dir)
+            .env("AU2_API_BASE", server.url(""))
+            .args(args)
+            .assert()
+            .success()
+    };
+
+    au2(&["prepare:aviutl2"]);
+    assert_eq!(
+        fs::read_to_string(project_dir.join("dev").join(".aviutl2-version"))?,
+        "latest"
+    );
+
+    let output = au2(&["aviutl2", "list", "--json"])
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(json[0]["version"], "2.0beta3");
+    assert_eq!(json[0]["installed"], serde_json::json!(["development"]));
+    assert_eq!(json[1]["installed"], serde_json::json!([]));
+
+    Ok(())
+}
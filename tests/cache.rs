@@ -0,0 +1,132 @@
+mod common;
+
+use assert_cmd::Command;
+use fs_err as fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+fn write_project(project_dir: &Path, url: &str) -> Result<(), std::io::Error> {
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"cache\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = \"{url}\"\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n"
+        )
+        .as_bytes(),
+    )
+}
+
+fn au2(project_dir: &Path, args: &[&str]) -> assert_cmd::assert::Assert {
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(project_dir)
+        .args(args)
+        .assert()
+}
+
+#[test]
+fn cache_migrates_legacy_entries() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("cache_migrate_project");
+    fs::create_dir_all(&project_dir)?;
+    let server = common::StubServer::start(|_| common::StubResponse::ok(b"fresh".to_vec()))?;
+    let url = server.url("/my_plugin.aux2");
+    write_project(&project_dir, &url)?;
+
+    // 以前のバージョンは URL の DefaultHasher のハッシュをファイル名に付けていた
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    url.hash(&mut hasher);
+    let legacy_name = format!("{:016x}_my_plugin.aux2", hasher.finish());
+    let cache_dir = project_dir.join(".aviutl2-cli").join("cache");
+    write_file(&cache_dir.join(&legacy_name), b"legacy")?;
+
+    // prune は設定ファイルにある URL のキャッシュを削除せずに移行する
+    au2(&project_dir, &["cache", "prune"]).success();
+    let output = au2(&project_dir, &["cache", "list", "--json"]).success();
+    let entries: serde_json::Value = serde_json::from_slice(&output.get_output().stdout)?;
+    assert_eq!(entries[0]["source"], url.as_str());
+
+    au2(&project_dir, &["prepare:artifacts"]).success();
+
+    assert!(server.requests().is_empty());
+    assert_eq!(
+        fs::read_to_string(
+            project_dir
+                .join("dev")
+                .join("data")
+                .join("Plugin")
+                .join("my_plugin.aux2")
+        )?,
+        "legacy"
+    );
+    assert!(!cache_dir.join(&legacy_name).exists());
+    let index: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(cache_dir.join("index.json"))?)?;
+    let path = index["entries"][&url]["path"].as_str().unwrap();
+    assert!(path.starts_with("http/"));
+    assert_eq!(fs::read_to_string(cache_dir.join(path))?, "legacy");
+
+    Ok(())
+}
+
+#[test]
+fn cache_list_prune_and_clean() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("cache_commands_project");
+    fs::create_dir_all(&project_dir)?;
+    let server = common::StubServer::start(|_| common::StubResponse::ok(b"downloaded".to_vec()))?;
+    let url = server.url("/my_plugin.aux2");
+    write_project(&project_dir, &url)?;
+    let cache_dir = project_dir.join(".aviutl2-cli").join("cache");
+    write_file(
+        &cache_dir.join("aviutl2").join("aviutl2-2.0beta1.zip"),
+        b"zip",
+    )?;
+
+    au2(&project_dir, &["prepare:artifacts"]).success();
+
+    let output = au2(&project_dir, &["cache", "list", "--json"]).success();
+    let entries: serde_json::Value = serde_json::from_slice(&output.get_output().stdout)?;
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["kind"], "http");
+    assert_eq!(entries[0]["source"], url.as_str());
+    assert_eq!(entries[0]["size"], 10);
+    assert!(entries[0]["last_used"].as_u64().unwrap() > 0);
+    assert_eq!(entries[1]["kind"], "aviutl2");
+    assert_eq!(entries[1]["source"], "2.0beta1");
+
+    write_file(
+        &cache_dir.join("http").join("orphan").join("stale.bin"),
+        b"x",
+    )?;
+    // 取得元の分からない以前の形式のキャッシュは期間で判断する
+    write_file(&cache_dir.join("0123456789abcdef_unknown.aux2"), b"legacy")?;
+    au2(&project_dir, &["cache", "prune"]).success();
+    assert!(!cache_dir.join("http").join("orphan").exists());
+    assert!(cache_dir.join("0123456789abcdef_unknown.aux2").exists());
+    let output = au2(&project_dir, &["cache", "list", "--json"]).success();
+    let entries: serde_json::Value = serde_json::from_slice(&output.get_output().stdout)?;
+    assert_eq!(entries.as_array().unwrap().len(), 2);
+
+    // AviUtl2 の zip も期間で削除される
+    au2(&project_dir, &["cache", "prune", "--older-than", "0s"]).success();
+    let output = au2(&project_dir, &["cache", "list", "--json"]).success();
+    let entries: serde_json::Value = serde_json::from_slice(&output.get_output().stdout)?;
+    assert_eq!(entries.as_array().unwrap().len(), 0);
+    assert!(!cache_dir.join("0123456789abcdef_unknown.aux2").exists());
+
+    au2(&project_dir, &["cache", "prune", "--older-than", "soon"]).failure();
+
+    au2(&project_dir, &["cache", "clean"]).success();
+    assert!(!cache_dir.exists());
+
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use assert_cmd::Command;
+use fs_err as fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+#[test]
+fn check_reports_schema_errors_with_location() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("check_schema_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"check\"\nversion = 1\n\n[artifacts.my_plugin]\nsource = { url = 3 }\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"cpy\"\nbuild = { grup = \"all\" }\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("check")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:3:1: project.version: 文字列 / テーブルである必要があります",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:6:12: artifacts.my_plugin.source.url: 文字列である必要があります",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:8:1: artifacts.my_plugin.placement_method:",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:9:1: artifacts.my_plugin.build: group が必要です",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:9:11: artifacts.my_plugin.build.grup: 不明なキーです（group の誤りではありませんか？）",
+        ))
+        .stderr(predicates::str::contains("5 件の問題があります"));
+
+    Ok(())
+}
+
+#[test]
+fn check_reports_inconsistent_references() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("check_semantic_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"check\"\nversion = \"0.1.0\"\n\n[artifacts.first]\nsource = \"first.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\nbuild = { group = \"missing\" }\n\n[artifacts.second]\nsource = \"second.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\n\n[artifacts.disabled]\nenabled = false\nsource = \"disabled.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\n\n[release]\ninclude = [\"first\", \"unknown\"]\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("check")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:8:11: artifacts.first.build.group: build_group.missing が定義されていません",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:12:1: artifacts.second.destination: artifacts.first と配置先が重複しています",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:20:21: release.include[1]: artifacts.unknown が定義されていません",
+        ))
+        .stderr(predicates::str::contains("3 件の問題があります"));
+
+    Ok(())
+}
+
+#[test]
+fn check_reports_undeclared_profiles() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("check_profiles_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"check\"\nversion = \"0.1.0\"\n\n[profiles.nightly]\ninherits = \"relase\"\n\n[profiles.lite]\ninclude = [\"missing\"]\n\n[artifacts.my_plugin]\nsource = \"my_plugin.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\n\n[artifacts.my_plugin.profiles.beta]\nenabled = false\n\n[release]\nprofile = \"lite2\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("check")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:6:1: profiles.nightly.inherits: profiles.relase が定義されていません",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:9:12: profiles.lite.include[0]: artifacts.missing が定義されていません",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:15:31: artifacts.my_plugin.profiles.beta: profiles.beta が定義されていません",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:19:1: release.profile: profiles.lite2 が定義されていません",
+        ))
+        .stderr(predicates::str::contains("4 件の問題があります"));
+
+    Ok(())
+}
+
+#[test]
+fn check_accepts_valid_config() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("check_valid_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"check\"\nversion = \"0.1.0\"\n\n[build_group]\nall = \"echo build\"\n\n[artifacts.my_plugin]\nsource = \"my_plugin.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\nbuild = { group = \"all\" }\n\n[release]\ninclude = [\"my_plugin\"]\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("check")
+        .assert()
+        .success()
+        .stdout("");
+
+    Ok(())
+}
+
+#[test]
+fn check_rejects_unknown_keys_with_suggestions() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("check_unknown_project");
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(&project_dir.join("my_plugin.aux2"), b"plugin")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"check\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = { url = \"https://example.com/my_plugin.aux2\", sha265 = \"00\" }\ndestination = \"Plugin/my_plugin.aux2\"\nplacment_method = \"copy\"\n\n[artifacts.my_plugin.profiles.release]\nbiuld = \"echo release\"\n\n[relase]\noutput_dir = \"out\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("check")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:6:56: artifacts.my_plugin.source.sha265: 不明なキーです（sha256 の誤りではありませんか？）",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:8:1: artifacts.my_plugin.placment_method: 不明なキーです（placement_method の誤りではありませんか？）",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:11:1: artifacts.my_plugin.profiles.release.biuld: 不明なキーです（build の誤りではありませんか？）",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:13:2: relase: 不明なキーです（release の誤りではありませんか？）",
+        ));
+
+    // check 以外では警告にとどめる
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"check\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = \"my_plugin.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\nplacment_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+    )?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "artifacts.my_plugin.placment_method: 不明なキーです（placement_method の誤りではありませんか？）",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn check_reports_destination_collisions_per_profile() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("check_profile_destination_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        br#"[project]
+name = "check"
+version = "0.1.0"
+
+[artifacts.a]
+source = "a.aux2"
+destination = "Plugin/a.aux2"
+
+[artifacts.b]
+source = "b.aux2"
+destination = "Plugin/b.aux2"
+
+[artifacts.b.profiles.release]
+destination = "Plugin/a.aux2"
+
+[artifacts.c]
+source = "c.aux2"
+destination = "Plugin/c.aux2"
+
+[artifacts.d]
+source = "d.aux2"
+destination = "Plugin/c.aux2"
+
+[artifacts.d.profiles.debug]
+destination = "Plugin/d.aux2"
+"#,
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("check")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:14:1: artifacts.b.profiles.release.destination: プロファイル release で artifacts.a と配置先が重複しています: Plugin/a.aux2",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.toml:22:1: artifacts.d.destination: プロファイル release で artifacts.c と配置先が重複しています: Plugin/c.aux2",
+        ))
+        .stderr(predicates::str::contains("2 件の問題があります"));
+
+    Ok(())
+}
@@ -0,0 +1,229 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// テスト用の簡易 HTTP サーバーが受け取ったリクエスト。
+#[derive(Clone, Debug)]
+pub struct StubRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+}
+
+impl StubRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+}
+
+pub struct StubResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl StubResponse {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// スレッド上で動く簡易 HTTP サーバー。テストプロセスの終了とともに停止します。
+pub struct StubServer {
+    pub port: u16,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+}
+
+impl StubServer {
+    pub fn start<F>(handler: F) -> Result<Self, std::io::Error>
+    where
+        F: Fn(&StubRequest) -> StubResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let handler = Arc::clone(&handler);
+                let recorded = Arc::clone(&recorded);
+                thread::spawn(move || {
+                    let _ = handle_connection(stream, handler.as_ref(), &recorded);
+                });
+            }
+        });
+        Ok(Self { port, requests })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn handle_connection<F>(
+    stream: TcpStream,
+    handler: &F,
+    recorded: &Mutex<Vec<StubRequest>>,
+) -> Result<(), std::io::Error>
+where
+    F: Fn(&StubRequest) -> StubResponse,
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    if let Some(length) = headers
+        .get("content-length")
+        .and_then(|v| v.parse::<usize>().ok())
+    {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+    }
+
+    let (path, query_string) = target.split_once('?').unwrap_or((&target, ""));
+    let query = query_string
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key.to_string(), value.to_string())
+        })
+        .collect();
+    let request = StubRequest {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+    };
+    recorded.lock().unwrap().push(request.clone());
+
+    let response = handler(&request);
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {} Stub\r\n", response.status)?;
+    for (name, value) in &response.headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    write!(
+        stream,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    )?;
+    if request.method != "HEAD" {
+        stream.write_all(&response.body)?;
+    }
+    stream.flush()
+}
+
+/// `files` の内容を持つ zip をメモリ上に作成します。
+pub fn zip_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut cursor = std::io::Cursor::new(Vec::new());
+    {
+        let mut zip = zip::ZipWriter::new(&mut cursor);
+        let options = zip::write::FileOptions::<()>::default();
+        for (name, content) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+    cursor.into_inner()
+}
+
+/// 接続を受け付けないポート番号を返します。
+pub fn unused_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// `exports` を公開する最小限の PE32+ の DLL を作成します。
+pub fn pe_dll(machine: u16, exports: &[&str]) -> Vec<u8> {
+    const SECTION_RVA: u32 = 0x1000;
+    const SECTION_OFFSET: usize = 0x200;
+
+    // エクスポートディレクトリ、名前の RVA の配列、名前の文字列の順に並べる
+    let mut section = vec![0u8; 40];
+    section[24..28].copy_from_slice(&(exports.len() as u32).to_le_bytes());
+    section[32..36].copy_from_slice(&(SECTION_RVA + 40).to_le_bytes());
+    let mut strings = Vec::new();
+    let strings_rva = SECTION_RVA + 40 + exports.len() as u32 * 4;
+    for name in exports {
+        let rva = strings_rva + strings.len() as u32;
+        section.extend_from_slice(&rva.to_le_bytes());
+        strings.extend_from_slice(name.as_bytes());
+        strings.push(0);
+    }
+    section.extend_from_slice(&strings);
+    let section_size = section.len() as u32;
+
+    let mut image = vec![0u8; SECTION_OFFSET];
+    image[0..2].copy_from_slice(b"MZ");
+    image[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+    image[0x40..0x44].copy_from_slice(b"PE\0\0");
+    let coff = 0x44;
+    image[coff..coff + 2].copy_from_slice(&machine.to_le_bytes());
+    image[coff + 2..coff + 4].copy_from_slice(&1u16.to_le_bytes());
+    image[coff + 16..coff + 18].copy_from_slice(&240u16.to_le_bytes());
+    image[coff + 18..coff + 20].copy_from_slice(&0x2022u16.to_le_bytes());
+    let optional = coff + 20;
+    image[optional..optional + 2].copy_from_slice(&0x20bu16.to_le_bytes());
+    image[optional + 108..optional + 112].copy_from_slice(&16u32.to_le_bytes());
+    image[optional + 112..optional + 116].copy_from_slice(&SECTION_RVA.to_le_bytes());
+    image[optional + 116..optional + 120].copy_from_slice(&section_size.to_le_bytes());
+    let header = optional + 240;
+    image[header..header + 6].copy_from_slice(b".edata");
+    image[header + 8..header + 12].copy_from_slice(&section_size.to_le_bytes());
+    image[header + 12..header + 16].copy_from_slice(&SECTION_RVA.to_le_bytes());
+    image[header + 16..header + 20].copy_from_slice(&section_size.to_le_bytes());
+    image[header + 20..header + 24].copy_from_slice(&(SECTION_OFFSET as u32).to_le_bytes());
+    image.extend_from_slice(&section);
+    image
+}
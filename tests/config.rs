@@ -0,0 +1,407 @@
+use assert_cmd::Command;
+use fs_err as fs;
+use predicates::prelude::PredicateBooleanExt;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+const CONFIG: &[u8] = b"[project]\nname = \"config\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = \"build/my_plugin.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n";
+
+#[test]
+fn config_is_found_from_subdirectory_in_dot_config() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("dot_config_project");
+    write_file(&project_dir.join(".config").join("aviutl2.toml"), CONFIG)?;
+    write_file(&project_dir.join("build").join("my_plugin.aux2"), b"plugin")?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    let subdir = project_dir.join("src").join("nested");
+    fs::create_dir_all(&subdir)?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&subdir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .success();
+
+    // 相対パスはプロジェクトのディレクトリが基準になる
+    assert_eq!(
+        fs::read_to_string(
+            project_dir
+                .join("dev")
+                .join("data")
+                .join("Plugin")
+                .join("my_plugin.aux2")
+        )?,
+        "plugin"
+    );
+    assert!(project_dir.join(".aviutl2-cli").exists());
+    assert!(!subdir.join("dev").exists());
+    assert!(!subdir.join(".aviutl2-cli").exists());
+
+    Ok(())
+}
+
+#[test]
+fn config_flag_selects_project() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("explicit_project");
+    write_file(&project_dir.join("custom.toml"), CONFIG)?;
+    write_file(&project_dir.join("build").join("my_plugin.aux2"), b"plugin")?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    let other_dir = temp.path().join("elsewhere");
+    fs::create_dir_all(&other_dir)?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&other_dir)
+        .args(["prepare:artifacts", "--force", "--config"])
+        .arg(project_dir.join("custom.toml"))
+        .assert()
+        .success();
+    assert!(
+        project_dir
+            .join("dev")
+            .join("data")
+            .join("Plugin")
+            .join("my_plugin.aux2")
+            .exists()
+    );
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&other_dir)
+        .args(["check", "--config", "missing.toml"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "設定ファイルが見つかりません: missing.toml",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn local_config_overrides_main_config() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("local_override_project");
+    write_file(&project_dir.join("aviutl2.toml"), CONFIG)?;
+    write_file(
+        &project_dir.join("aviutl2.local.toml"),
+        b"[development]\ninstall_dir = \"my-dev\"\n",
+    )?;
+    write_file(&project_dir.join("build").join("my_plugin.aux2"), b"plugin")?;
+    write_file(&project_dir.join("my-dev").join("aviutl2.exe"), b"")?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .success();
+    assert!(
+        project_dir
+            .join("my-dev")
+            .join("data")
+            .join("Plugin")
+            .join("my_plugin.aux2")
+            .exists()
+    );
+    assert!(!project_dir.join("dev").exists());
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["config", "show", "--origin"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "aviutl2_version = \"latest\"  # aviutl2.toml",
+        ))
+        .stdout(predicates::str::contains(
+            "install_dir = \"my-dev\"  # aviutl2.local.toml",
+        ));
+
+    // ローカルの設定ファイルの問題はそのファイルの位置で報告する
+    write_file(
+        &project_dir.join("aviutl2.local.toml"),
+        b"[development]\ninstall_dir = 1\ninstal_dir = \"my-dev\"\n",
+    )?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("check")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "aviutl2.local.toml:2:1: development.install_dir: 文字列である必要があります",
+        ))
+        .stdout(predicates::str::contains(
+            "aviutl2.local.toml:3:1: development.instal_dir: 不明なキーです（install_dir の誤りではありませんか？）",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn config_values_expand_environment_and_templates() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("template_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"tmpl\"\nversion = \"0.2.0\"\n\n[artifacts.plugin]\nsource = \"build/{name}-{profile}.aux2\"\ndestination = \"${env:AU2_TEST_PLUGIN_DIR:-Plugin}/{name}.aux2\"\nplacement_method = \"copy\"\nbuild = \"echo {version}> build/{name}-{profile}.aux2\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"${env:AU2_TEST_DEV_DIR}\"\n\n[preview]\ninstall_dir = \"${env:AU2_TEST_PREVIEW_DIR}\"\n",
+    )?;
+    fs::create_dir_all(project_dir.join("build"))?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_TEST_DEV_DIR", "dev")
+        .env_remove("AU2_TEST_PLUGIN_DIR")
+        // develop では使わない値の環境変数は設定されていなくてもよい
+        .env_remove("AU2_TEST_PREVIEW_DIR")
+        .args(["develop", "--skip-start", "--profile", "release"])
+        .assert()
+        .success();
+    let copied = project_dir
+        .join("dev")
+        .join("data")
+        .join("Plugin")
+        .join("tmpl.aux2");
+    assert_eq!(fs::read_to_string(&copied)?.trim(), "0.2.0");
+    assert!(project_dir.join("build").join("tmpl-release.aux2").exists());
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env_remove("AU2_TEST_DEV_DIR")
+        .args(["develop", "--skip-start"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "環境変数 AU2_TEST_DEV_DIR が設定されていません",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn config_show_prints_resolved_settings() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("resolved_project");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        br#"[project]
+name = "demo"
+version = "0.3.0"
+
+[profiles.nightly]
+inherits = "release"
+env = { NIGHTLY = "1" }
+
+[build_group]
+all = ["echo {profile}"]
+
+[artifacts.plugin]
+source = "target/{profile}/plugin.dll"
+destination = "Plugin/{name}.aux2"
+build = { group = "all" }
+
+[artifacts.plugin.profiles.debug]
+destination = "Plugin/{name}_debug.aux2"
+placement_method = "copy"
+
+[artifacts.remote]
+source = { url = "https://example.com/remote.zip", headers = { Authorization = "Bearer secret" } }
+destination = "Script/remote"
+
+[artifacts.shared]
+source = { project = "../library", artifact = "helper" }
+destination = "Script/shared.lua"
+
+[development]
+aviutl2_version = "latest"
+install_dir = "dev"
+prebuild = "echo prebuild {version}"
+
+[release]
+zip_name = "{name}-{profile}-v{version}"
+include = ["plugin"]
+"#,
+    )?;
+
+    let library_dir = temp.path().join("library");
+    write_file(
+        &library_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"library\"\nversion = \"0.1.0\"\n\n[artifacts.helper]\nsource = \"out/{profile}/helper.lua\"\ndestination = \"Script/helper.lua\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["config", "show", "--profile", "debug"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("install_dir = \"dev\""))
+        .stdout(predicates::str::contains(
+            "prebuild = [\"echo prebuild 0.3.0\"]",
+        ))
+        .stdout(predicates::str::contains(
+            "destination = \"Plugin/demo_debug.aux2\"",
+        ))
+        .stdout(predicates::str::contains("placement_method = \"copy\""))
+        .stdout(predicates::str::contains("build = [\"echo debug\"]"))
+        .stdout(predicates::str::contains("Authorization = \"********\""))
+        .stdout(predicates::str::contains("secret").not());
+
+    // 何も指定しなければ develop と development.profile（デフォルトは debug）の設定を表示する
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["config", "show", "--json"])
+        .output()?;
+    assert!(output.status.success());
+    let settings: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(settings["command"], "develop");
+    assert_eq!(settings["profile"], "debug");
+    assert_eq!(settings["install_dir"], "dev");
+    assert_eq!(
+        settings["prebuild"],
+        serde_json::json!(["echo prebuild 0.3.0"])
+    );
+    let shared = settings["artifacts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|artifact| artifact["name"] == "shared")
+        .unwrap();
+    // 別プロジェクトの成果物は、参照先で配置されるファイルのパスに解決する
+    assert_eq!(
+        shared["source"],
+        fs::canonicalize(&library_dir)?
+            .join("out/debug/helper.lua")
+            .display()
+            .to_string()
+    );
+    assert_eq!(shared["destination"], "Script/shared.lua");
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["config", "show"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("command = \"develop\""))
+        .stdout(predicates::str::contains("[development]").not());
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args([
+            "config",
+            "show",
+            "--command",
+            "release",
+            "--profile",
+            "nightly",
+            "--json",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let settings: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(settings["inherits"], serde_json::json!(["release"]));
+    assert_eq!(settings["env"]["NIGHTLY"], "1");
+    assert_eq!(
+        settings["package"],
+        "release/demo-nightly-v0.3.0.au2pkg.zip"
+    );
+    assert_eq!(
+        settings["artifacts"],
+        serde_json::json!([{
+            "name": "plugin",
+            "source": "target/nightly/plugin.dll",
+            "destination": "Plugin/demo.aux2",
+            "placement_method": "symlink",
+            "build": ["echo nightly"],
+        }])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn migrate_rewrites_old_config_keeping_comments() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("migrate_project");
+    let original = "#:schema ./.aviutl2-cli/aviutl2.schema.json\n# 先頭のコメント\n[project]\nname = \"migrate\" # 名前\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = \"build/my_plugin.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\n\n# nightly 用の上書き\n[artifacts.my_plugin.profiles.nightly]\nsource = \"build/nightly.aux2\"\n\n[development]\naviutl2_version = \"latest\"\nprofile = \"nightly\"\n";
+    let config_path = project_dir.join("aviutl2.toml");
+    write_file(&config_path, original.as_bytes())?;
+
+    // schema_version がない設定ファイルでは、読み込めなくても移行を案内する
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["config", "show"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "schema_version がないため古い形式として扱います。`au2 migrate` で更新できます",
+        ));
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["migrate", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("+schema_version = 1"));
+    assert_eq!(fs::read_to_string(&config_path)?, original);
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("migrate")
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("--- ").and(predicates::str::contains("+[profiles.nightly]")),
+        );
+    let migrated = fs::read_to_string(&config_path)?;
+    assert!(migrated.starts_with(
+        "#:schema ./.aviutl2-cli/aviutl2.schema.json\n# 先頭のコメント\nschema_version = 1\n"
+    ));
+    assert!(migrated.contains("name = \"migrate\" # 名前\n"));
+    assert!(migrated.contains("# nightly 用の上書き\n[artifacts.my_plugin.profiles.nightly]\n"));
+    assert!(migrated.ends_with("\n[profiles.nightly]\n"));
+
+    // 2 回目は何も変更しない
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("migrate")
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(fs::read_to_string(&config_path)?, migrated);
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("check")
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn newer_schema_version_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("newer_schema_project");
+    let mut config = b"schema_version = 999\n".to_vec();
+    config.extend_from_slice(CONFIG);
+    write_file(&project_dir.join("aviutl2.toml"), &config)?;
+
+    for args in [
+        &["config", "show", "--command", "develop"][..],
+        &["migrate"],
+    ] {
+        Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+            .current_dir(&project_dir)
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains("au2 を更新してください"));
+    }
+
+    Ok(())
+}
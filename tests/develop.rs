@@ -0,0 +1,224 @@
+use assert_cmd::Command;
+use fs_err as fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+#[test]
+fn develop_builds_artifact_from_other_project() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let common_dir = temp.path().join("common-lib");
+    write_file(
+        &common_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"common\"\nversion = \"0.1.0\"\n\n[artifacts.common_aux2]\nsource = \"common.aux2\"\ndestination = \"Plugin/common.aux2\"\n\n[artifacts.common_aux2.profiles.debug]\nbuild = \"echo debug> common.aux2\"\n\n[artifacts.common_aux2.profiles.release]\nbuild = \"echo release> common.aux2\"\n",
+    )?;
+
+    let project_dir = temp.path().join("suite");
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"suite\"\nversion = \"0.1.0\"\n\n[artifacts.common]\nsource = { project = \"../common-lib\", artifact = \"common_aux2\" }\ndestination = \"Plugin/suite/common.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["develop", "--skip-start", "--profile", "release"])
+        .assert()
+        .success();
+
+    // ビルドは参照先のディレクトリで実行される
+    assert!(common_dir.join("common.aux2").exists());
+    assert!(!project_dir.join("common.aux2").exists());
+    let copied = project_dir
+        .join("dev")
+        .join("data")
+        .join("Plugin")
+        .join("suite")
+        .join("common.aux2");
+    assert_eq!(fs::read_to_string(&copied)?.trim(), "release");
+
+    Ok(())
+}
+
+#[test]
+fn develop_rejects_cyclic_project_sources() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("a");
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"a\"\nversion = \"0.1.0\"\n\n[artifacts.x]\nsource = { project = \"../b\", artifact = \"y\" }\ndestination = \"Plugin/x.aux2\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+    )?;
+    write_file(
+        &temp.path().join("b").join("aviutl2.toml"),
+        b"[project]\nname = \"b\"\nversion = \"0.1.0\"\n\n[artifacts.y]\nsource = { project = \"../a\", artifact = \"x\" }\ndestination = \"Plugin/y.aux2\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["develop", "--skip-start"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("循環参照"));
+
+    Ok(())
+}
+
+#[test]
+fn develop_builds_cargo_source_into_custom_target_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("cargo_project");
+    write_file(
+        &project_dir.join("Cargo.toml"),
+        b"[package]\nname = \"my-plugin\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[lib]\ncrate-type = [\"cdylib\"]\n\n[workspace]\n",
+    )?;
+    write_file(
+        &project_dir.join("src").join("lib.rs"),
+        b"#[no_mangle]\npub extern \"C\" fn my_plugin() -> i32 {\n    42\n}\n",
+    )?;
+    write_file(
+        &project_dir.join(".cargo").join("config.toml"),
+        b"[build]\ntarget-dir = \"build-out\"\n",
+    )?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"cargo\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = { cargo = \"my-plugin\" }\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n\n[profiles.nightly]\ninherits = \"release\"\n\n[profiles.custom]\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env_remove("CARGO_TARGET_DIR")
+        .env_remove("CARGO_BUILD_TARGET")
+        .args(["develop", "--skip-start"])
+        .assert()
+        .success();
+
+    let (prefix, suffix) = if cfg!(windows) {
+        ("", ".dll")
+    } else if cfg!(target_vendor = "apple") {
+        ("lib", ".dylib")
+    } else {
+        ("lib", ".so")
+    };
+    let built = project_dir
+        .join("build-out")
+        .join("debug")
+        .join(format!("{prefix}my_plugin{suffix}"));
+    assert!(built.exists());
+    let copied = project_dir
+        .join("dev")
+        .join("data")
+        .join("Plugin")
+        .join("my_plugin.aux2");
+    assert_eq!(fs::read(&copied)?, fs::read(&built)?);
+
+    // 独自のプロファイルは継承元の組み込みのプロファイルに対応する cargo のプロファイルでビルドする
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env_remove("CARGO_TARGET_DIR")
+        .env_remove("CARGO_BUILD_TARGET")
+        .args(["develop", "--skip-start", "--profile", "nightly"])
+        .assert()
+        .success();
+    assert!(
+        project_dir
+            .join("build-out")
+            .join("release")
+            .join(format!("{prefix}my_plugin{suffix}"))
+            .exists()
+    );
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["develop", "--skip-start", "--profile", "custom"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cargo_profile の指定が必要です"));
+
+    Ok(())
+}
+
+#[test]
+fn develop_uses_declared_profiles_with_inheritance() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("profiles_project");
+    let build = if cfg!(windows) {
+        "echo %AU2_TEST_FLAVOR%> plugin.aux2"
+    } else {
+        "echo $AU2_TEST_FLAVOR> plugin.aux2"
+    };
+    write_file(&project_dir.join("extra.txt"), b"extra")?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"profiles\"\nversion = \"0.1.0\"\n\n[profiles.nightly]\ninherits = \"release\"\nenv = {{ AU2_TEST_FLAVOR = \"nightly\" }}\ninclude = [\"plugin\"]\n\n[artifacts.plugin]\ndestination = \"Plugin/plugin.aux2\"\nplacement_method = \"copy\"\n\n[artifacts.plugin.profiles.release]\nsource = \"plugin.aux2\"\nbuild = \"{build}\"\n\n[artifacts.extra]\nsource = \"extra.txt\"\ndestination = \"Script/extra.txt\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n"
+        )
+        .as_bytes(),
+    )?;
+
+    // nightly は release の上書きを引き継ぎ、include にない成果物は配置しない
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env_remove("AU2_TEST_FLAVOR")
+        .args(["develop", "--skip-start", "--profile", "nightly"])
+        .assert()
+        .success();
+    let data_dir = project_dir.join("dev").join("data");
+    assert_eq!(
+        fs::read_to_string(data_dir.join("Plugin").join("plugin.aux2"))?.trim(),
+        "nightly"
+    );
+    assert!(!data_dir.join("Script").join("extra.txt").exists());
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["develop", "--skip-start", "--profile", "nightyl"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "プロファイル nightyl が定義されていません",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn artifact_profiles_override_destination_and_placement() -> Result<(), Box<dyn std::error::Error>>
+{
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("profile_destination_project");
+    write_file(&project_dir.join("plugin.aux2"), b"plugin")?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"profiles\"\nversion = \"0.1.0\"\n\n[artifacts.plugin]\nsource = \"plugin.aux2\"\ndestination = \"Plugin/plugin.aux2\"\nplacement_method = \"symlink\"\n\n[artifacts.plugin.profiles.debug]\ndestination = \"Plugin/plugin_debug.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+    )?;
+    let plugin_dir = project_dir.join("dev").join("data").join("Plugin");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force", "--profile", "debug"])
+        .assert()
+        .success();
+    let debug = fs::symlink_metadata(plugin_dir.join("plugin_debug.aux2"))?;
+    assert!(debug.file_type().is_file());
+    assert!(!plugin_dir.join("plugin.aux2").exists());
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force", "--profile", "release"])
+        .assert()
+        .success();
+    let release = fs::symlink_metadata(plugin_dir.join("plugin.aux2"))?;
+    assert!(release.file_type().is_symlink());
+
+    Ok(())
+}
@@ -0,0 +1,447 @@
+mod common;
+
+use assert_cmd::Command;
+use fs_err as fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tempfile::tempdir;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+#[test]
+fn download_retries_transient_server_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("download_retry_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&calls);
+    let server = common::StubServer::start(move |_| {
+        if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            common::StubResponse::status(503)
+        } else {
+            common::StubResponse::ok(b"downloaded".to_vec())
+        }
+    })?;
+
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"download\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = \"{}\"\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+            server.url("/my_plugin.aux2")
+        )
+        .as_bytes(),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("prepare:artifacts")
+        .assert()
+        .success();
+
+    let copied = project_dir
+        .join("dev")
+        .join("data")
+        .join("Plugin")
+        .join("my_plugin.aux2");
+    assert_eq!(fs::read(&copied)?, b"downloaded");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn download_resumes_partial_file_with_range_request() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("download_resume_project");
+    fs::create_dir_all(&project_dir)?;
+
+    let archive = common::zip_bytes(&[("aviutl2.exe", &[b'x'; 4096])]);
+    let split = archive.len() / 2;
+    let served = archive.clone();
+    let server = common::StubServer::start(move |request| {
+        let start = request
+            .header("range")
+            .filter(|_| request.header("if-range") == Some("\"v1\""))
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+        match start {
+            Some(start) => common::StubResponse {
+                status: 206,
+                headers: vec![(
+                    "Content-Range".to_string(),
+                    format!("bytes {}-{}/{}", start, served.len() - 1, served.len()),
+                )],
+                body: served[start..].to_vec(),
+            },
+            None => common::StubResponse::ok(served.clone()).header("ETag", "\"v1\""),
+        }
+    })?;
+
+    let partial = project_dir
+        .join(".aviutl2-cli")
+        .join("cache")
+        .join("aviutl2")
+        .join("aviutl2-2.0beta3.zip.partial");
+    write_file(&partial, &archive[..split])?;
+    write_file(
+        &partial.with_extension("partial.json"),
+        format!(
+            "{{\"url\":\"{}\",\"etag\":\"\\\"v1\\\"\"}}",
+            server.url("/download")
+        )
+        .as_bytes(),
+    )?;
+
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"download\"\nversion = \"0.1.0\"\n\n[artifacts]\n\n[development]\naviutl2_version = \"2.0beta3\"\ninstall_dir = \"dev\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_API_BASE", server.url(""))
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].header("range"),
+        Some(format!("bytes={split}-").as_str())
+    );
+    assert_eq!(requests[0].header("if-range"), Some("\"v1\""));
+    assert_eq!(
+        fs::read(project_dir.join("dev").join("aviutl2.exe"))?,
+        vec![b'x'; 4096]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn download_restarts_when_content_range_does_not_match_offset()
+-> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("download_range_mismatch_project");
+    fs::create_dir_all(&project_dir)?;
+
+    let archive = common::zip_bytes(&[("aviutl2.exe", &[b'z'; 4096])]);
+    let split = archive.len() / 2;
+    let served = archive.clone();
+    // Range を要求されても先頭からの範囲を返す
+    let server = common::StubServer::start(move |request| {
+        if request.header("range").is_some() {
+            common::StubResponse {
+                status: 206,
+                headers: vec![(
+                    "Content-Range".to_string(),
+                    format!("bytes 0-{}/{}", split - 1, served.len()),
+                )],
+                body: served[..split].to_vec(),
+            }
+        } else {
+            common::StubResponse::ok(served.clone()).header("ETag", "\"v1\"")
+        }
+    })?;
+
+    let partial = project_dir
+        .join(".aviutl2-cli")
+        .join("cache")
+        .join("aviutl2")
+        .join("aviutl2-2.0beta3.zip.partial");
+    write_file(&partial, &archive[..split])?;
+    write_file(
+        &partial.with_extension("partial.json"),
+        format!(
+            "{{\"url\":\"{}\",\"etag\":\"\\\"v1\\\"\"}}",
+            server.url("/download")
+        )
+        .as_bytes(),
+    )?;
+
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"download\"\nversion = \"0.1.0\"\n\n[artifacts]\n\n[development]\naviutl2_version = \"2.0beta3\"\ninstall_dir = \"dev\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_API_BASE", server.url(""))
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[0].header("range"),
+        Some(format!("bytes={split}-").as_str())
+    );
+    assert_eq!(requests[1].header("range"), None);
+    assert_eq!(
+        fs::read(project_dir.join("dev").join("aviutl2.exe"))?,
+        vec![b'z'; 4096]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn download_restarts_partial_file_when_content_changed() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("download_stale_partial_project");
+    fs::create_dir_all(&project_dir)?;
+
+    let archive = common::zip_bytes(&[("aviutl2.exe", &[b'y'; 4096])]);
+    let served = archive.clone();
+    // If-Range が一致しないので Range を無視して全体を返す
+    let server = common::StubServer::start(move |_| {
+        common::StubResponse::ok(served.clone()).header("ETag", "\"new\"")
+    })?;
+
+    let partial = project_dir
+        .join(".aviutl2-cli")
+        .join("cache")
+        .join("aviutl2")
+        .join("aviutl2-latest.zip.partial");
+    write_file(&partial, b"stale data from the previous release")?;
+    write_file(
+        &partial.with_extension("partial.json"),
+        format!(
+            "{{\"url\":\"{}\",\"etag\":\"\\\"old\\\"\"}}",
+            server.url("/download")
+        )
+        .as_bytes(),
+    )?;
+
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"download\"\nversion = \"0.1.0\"\n\n[artifacts]\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_API_BASE", server.url(""))
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("if-range"), Some("\"old\""));
+    assert_eq!(
+        fs::read(project_dir.join("dev").join("aviutl2.exe"))?,
+        vec![b'y'; 4096]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn download_keeps_previous_cache_when_checksum_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("download_checksum_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    let broken = Arc::new(AtomicUsize::new(0));
+    let current = Arc::clone(&broken);
+    let server = common::StubServer::start(move |_| {
+        if current.load(Ordering::SeqCst) == 0 {
+            common::StubResponse::ok(b"good".to_vec())
+        } else {
+            common::StubResponse::ok(b"tampered".to_vec())
+        }
+    })?;
+    // "good" の xxh3-128
+    let xxh3 = format!("{:032x}", xxhash_rust::xxh3::xxh3_128(b"good"));
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"download\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = {{ url = \"{}\", xxh3 = \"{}\" }}\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+            server.url("/my_plugin.aux2"),
+            xxh3
+        )
+        .as_bytes(),
+    )?;
+    let prepare = |args: &[&str]| {
+        Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+            .current_dir(&project_dir)
+            .arg("prepare:artifacts")
+            .args(args)
+            .assert()
+    };
+
+    prepare(&["--force"]).success();
+    broken.store(1, Ordering::SeqCst);
+    prepare(&["--force", "--refresh"]).failure();
+
+    // 検証に失敗しても以前のキャッシュは残る
+    let requests = server.requests().len();
+    prepare(&["--force"]).success();
+    assert_eq!(server.requests().len(), requests);
+    assert_eq!(
+        fs::read(
+            project_dir
+                .join("dev")
+                .join("data")
+                .join("Plugin")
+                .join("my_plugin.aux2")
+        )?,
+        b"good"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn download_revalidates_cached_source_with_etag() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("download_revalidate_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    let revision = Arc::new(AtomicUsize::new(1));
+    let current = Arc::clone(&revision);
+    let server = common::StubServer::start(move |request| {
+        let revision = current.load(Ordering::SeqCst);
+        let etag = format!("\"v{revision}\"");
+        if request.header("if-none-match") == Some(etag.as_str()) {
+            common::StubResponse::status(304)
+        } else {
+            common::StubResponse::ok(format!("revision {revision}")).header("ETag", &etag)
+        }
+    })?;
+
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"download\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = {{ url = \"{}\", cache = \"revalidate\" }}\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+            server.url("/my_plugin.aux2")
+        )
+        .as_bytes(),
+    )?;
+    let copied = project_dir
+        .join("dev")
+        .join("data")
+        .join("Plugin")
+        .join("my_plugin.aux2");
+    let prepare = || {
+        Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+            .current_dir(&project_dir)
+            .args(["prepare:artifacts", "--force"])
+            .assert()
+            .success();
+    };
+
+    prepare();
+    assert_eq!(fs::read_to_string(&copied)?, "revision 1");
+
+    prepare();
+    assert_eq!(fs::read_to_string(&copied)?, "revision 1");
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].header("if-none-match"), Some("\"v1\""));
+
+    revision.store(2, Ordering::SeqCst);
+    prepare();
+    assert_eq!(fs::read_to_string(&copied)?, "revision 2");
+
+    Ok(())
+}
+
+#[test]
+fn download_sends_configured_headers_without_leaking_them() -> Result<(), Box<dyn std::error::Error>>
+{
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("download_headers_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    let server = common::StubServer::start(|request| {
+        if request.header("authorization") == Some("Bearer host-secret")
+            && request.header("x-api-key") == Some("source-secret")
+        {
+            common::StubResponse::ok(b"private".to_vec())
+        } else {
+            common::StubResponse::status(401)
+        }
+    })?;
+    let credentials = temp.path().join("credentials.toml");
+    write_file(
+        &credentials,
+        format!(
+            "[hosts.\"127.0.0.1:{}\"]\ntoken = \"${{env:AU2_TEST_HOST_TOKEN}}\"\n",
+            server.port
+        )
+        .as_bytes(),
+    )?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"download\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = {{ url = \"{}\", headers = {{ X-Api-Key = \"${{env:AU2_TEST_SOURCE_KEY}}\" }} }}\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+            server.url("/my_plugin.aux2")
+        )
+        .as_bytes(),
+    )?;
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_CREDENTIALS", &credentials)
+        .env("AU2_TEST_HOST_TOKEN", "host-secret")
+        .env("AU2_TEST_SOURCE_KEY", "source-secret")
+        .env("RUST_LOG", "debug")
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .success();
+    let output = output.get_output();
+    let logs = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!logs.contains("secret"));
+    assert_eq!(
+        fs::read_to_string(
+            project_dir
+                .join("dev")
+                .join("data")
+                .join("Plugin")
+                .join("my_plugin.aux2")
+        )?,
+        "private"
+    );
+    let index = fs::read_to_string(
+        project_dir
+            .join(".aviutl2-cli")
+            .join("cache")
+            .join("index.json"),
+    )?;
+    assert!(!index.contains("secret"));
+    let snapshot = fs::read_to_string(
+        project_dir
+            .join(".aviutl2-cli")
+            .join("prepare-artifacts.json"),
+    )?;
+    assert!(!snapshot.contains("secret"));
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_CREDENTIALS", &credentials)
+        .env("AU2_TEST_HOST_TOKEN", "host-secret")
+        .env_remove("AU2_TEST_SOURCE_KEY")
+        .args(["prepare:artifacts", "--force", "--refresh"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("AU2_TEST_SOURCE_KEY"));
+
+    Ok(())
+}
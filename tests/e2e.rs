@@ -33,6 +33,8 @@ fn e2e_init_creates_config_and_updates_gitignore() -> Result<(), Box<dyn std::er
     let gitignore = fs::read_to_string(&gitignore_path)?;
     assert!(gitignore.contains("/.aviutl2-cli"));
     assert!(gitignore.contains("/release"));
+    assert!(gitignore.contains("/aviutl2.local.toml"));
+    assert!(gitignore.contains("/.config/aviutl2.local.toml"));
 
     Ok(())
 }
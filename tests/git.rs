@@ -0,0 +1,206 @@
+use assert_cmd::Command;
+use fs_err as fs;
+use std::path::Path;
+use std::process::Command as ProcessCommand;
+use tempfile::tempdir;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+fn can_run_git() -> bool {
+    ProcessCommand::new("git")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = ProcessCommand::new("git")
+        .current_dir(repo)
+        .args([
+            "-c",
+            "user.name=au2",
+            "-c",
+            "user.email=au2@example.com",
+            "-c",
+            "commit.gpgsign=false",
+        ])
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn commit_helper(repo: &Path, content: &str) -> Result<String, Box<dyn std::error::Error>> {
+    write_file(&repo.join("lib").join("helper.lua"), content.as_bytes())?;
+    git(repo, &["add", "."])?;
+    git(repo, &["commit", "--quiet", "-m", content])?;
+    git(repo, &["rev-parse", "HEAD"])
+}
+
+#[test]
+fn git_source_checks_out_locked_revision() -> Result<(), Box<dyn std::error::Error>> {
+    if !can_run_git() {
+        eprintln!("git が利用できないためスキップします");
+        return Ok(());
+    }
+
+    let temp = tempdir()?;
+    let repo = temp.path().join("helpers");
+    fs::create_dir_all(&repo)?;
+    git(&repo, &["init", "--quiet", "-b", "main"])?;
+    let first = commit_helper(&repo, "-- v1")?;
+
+    let project_dir = temp.path().join("git_source_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"git\"\nversion = \"0.1.0\"\n\n[artifacts.helper]\nsource = {{ git = \"{}\", rev = \"main\", path = \"lib/helper.lua\" }}\ndestination = \"Script/helper.lua\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+            repo.display().to_string().replace('\\', "/")
+        )
+        .as_bytes(),
+    )?;
+    let copied = project_dir
+        .join("dev")
+        .join("data")
+        .join("Script")
+        .join("helper.lua");
+    let prepare = |refresh: bool| {
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin!("au2"));
+        command
+            .current_dir(&project_dir)
+            .args(["prepare:artifacts", "--force"]);
+        if refresh {
+            command.arg("--refresh");
+        }
+        command.assert().success();
+    };
+
+    prepare(false);
+    assert_eq!(fs::read_to_string(&copied)?, "-- v1");
+    let lockfile = fs::read_to_string(project_dir.join("aviutl2.lock"))?;
+    assert!(lockfile.contains(&format!("commit = \"{first}\"")));
+    assert!(lockfile.contains("rev = \"main\""));
+
+    // ブランチが進んでもロックしたコミットを使い続ける
+    let second = commit_helper(&repo, "-- v2")?;
+    prepare(false);
+    assert_eq!(fs::read_to_string(&copied)?, "-- v1");
+
+    prepare(true);
+    assert_eq!(fs::read_to_string(&copied)?, "-- v2");
+    let lockfile = fs::read_to_string(project_dir.join("aviutl2.lock"))?;
+    assert!(lockfile.contains(&format!("commit = \"{second}\"")));
+
+    Ok(())
+}
+
+#[test]
+fn git_source_of_other_project_resolves_in_that_project() -> Result<(), Box<dyn std::error::Error>>
+{
+    if !can_run_git() {
+        eprintln!("git が利用できないためスキップします");
+        return Ok(());
+    }
+
+    let temp = tempdir()?;
+    let repo = temp.path().join("helpers");
+    fs::create_dir_all(&repo)?;
+    git(&repo, &["init", "--quiet", "-b", "main"])?;
+    let commit = commit_helper(&repo, "-- v1")?;
+
+    // 参照先のプロジェクトの git は参照先のディレクトリからの相対パス
+    let library_dir = temp.path().join("library");
+    write_file(
+        &library_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"library\"\nversion = \"0.1.0\"\n\n[artifacts.helper]\nsource = { git = \"../helpers\", rev = \"main\", path = \"lib/helper.lua\" }\ndestination = \"Script/helper.lua\"\n\n[artifacts.disabled]\nsource = \"disabled.lua\"\ndestination = \"Script/disabled.lua\"\nenabled = false\n",
+    )?;
+    let project_dir = temp.path().join("projects").join("suite");
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"suite\"\nversion = \"0.1.0\"\n\n[artifacts.helper]\nsource = { project = \"../../library\", artifact = \"helper\" }\ndestination = \"Script/suite/helper.lua\"\nplacement_method = \"copy\"\n\n[artifacts.disabled]\nsource = { project = \"../../library\", artifact = \"disabled\" }\ndestination = \"Script/suite/disabled.lua\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "artifacts.disabled は無効になっているため配置しません",
+        ));
+
+    let script_dir = project_dir
+        .join("dev")
+        .join("data")
+        .join("Script")
+        .join("suite");
+    assert_eq!(fs::read_to_string(script_dir.join("helper.lua"))?, "-- v1");
+    assert!(!script_dir.join("disabled.lua").exists());
+    let lockfile = fs::read_to_string(library_dir.join("aviutl2.lock"))?;
+    assert!(lockfile.contains(&format!("commit = \"{commit}\"")));
+    assert!(library_dir.join(".aviutl2-cli").join("git").exists());
+    assert!(!project_dir.join("aviutl2.lock").exists());
+    assert!(!project_dir.join(".aviutl2-cli").join("git").exists());
+
+    Ok(())
+}
+
+#[test]
+fn git_source_fetches_unreachable_commit_by_hash() -> Result<(), Box<dyn std::error::Error>> {
+    if !can_run_git() {
+        eprintln!("git が利用できないためスキップします");
+        return Ok(());
+    }
+
+    let temp = tempdir()?;
+    let repo = temp.path().join("helpers");
+    fs::create_dir_all(&repo)?;
+    git(&repo, &["init", "--quiet", "-b", "main"])?;
+    git(&repo, &["config", "uploadpack.allowAnySHA1InWant", "true"])?;
+    commit_helper(&repo, "-- main")?;
+    // どのブランチからも辿れないコミットを作る
+    git(&repo, &["checkout", "--quiet", "-b", "topic"])?;
+    let orphan = commit_helper(&repo, "-- topic")?;
+    git(&repo, &["checkout", "--quiet", "main"])?;
+    git(&repo, &["branch", "--quiet", "-D", "topic"])?;
+
+    // ローカルのパスで clone すると到達できないオブジェクトもコピーされるので file:// を使う
+    let project_dir = temp.path().join("git_sha_project");
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"git\"\nversion = \"0.1.0\"\n\n[artifacts.helper]\nsource = {{ git = \"file://{}\", rev = \"{orphan}\", path = \"lib/helper.lua\" }}\ndestination = \"Script/helper.lua\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+            repo.display().to_string().replace('\\', "/")
+        )
+        .as_bytes(),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(
+            project_dir
+                .join("dev")
+                .join("data")
+                .join("Script")
+                .join("helper.lua")
+        )?,
+        "-- topic"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use assert_cmd::Command;
+use fs_err as fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+fn release_server() -> Result<common::StubServer, std::io::Error> {
+    let archive = common::zip_bytes(&[("my_plugin.aux2", b"from github")]);
+    common::StubServer::start(move |request| {
+        let host = request.header("host").unwrap_or_default().to_string();
+        let release = |tag: &str| {
+            serde_json::json!({
+                "tag_name": tag,
+                "assets": [
+                    {
+                        "name": format!("my_plugin-{tag}.zip"),
+                        "url": format!("http://{host}/repos/owner/repo/releases/assets/1"),
+                        "browser_download_url": format!("http://{host}/download/{tag}/my_plugin-{tag}.zip"),
+                    },
+                    {
+                        "name": format!("my_plugin-{tag}.zip.sha256"),
+                        "url": format!("http://{host}/repos/owner/repo/releases/assets/2"),
+                        "browser_download_url": format!("http://{host}/download/{tag}/my_plugin-{tag}.zip.sha256"),
+                    },
+                ],
+            })
+            .to_string()
+        };
+        match request.path.as_str() {
+            "/repos/owner/repo/releases/tags/v1.0.0" => common::StubResponse::ok(release("v1.0.0")),
+            "/repos/owner/repo/releases/latest" => common::StubResponse::ok(release("v2.0.0")),
+            path if path.starts_with("/download/") && path.ends_with(".zip") => {
+                common::StubResponse::ok(archive.clone())
+            }
+            "/repos/owner/repo/releases/assets/1"
+                if request.header("accept") == Some("application/octet-stream")
+                    && request.header("authorization").is_some() =>
+            {
+                common::StubResponse::ok(archive.clone())
+            }
+            _ => common::StubResponse::status(404),
+        }
+    })
+}
+
+fn config(source: &str) -> String {
+    format!(
+        "[project]\nname = \"github\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = {source}\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n"
+    )
+}
+
+#[test]
+fn github_source_downloads_release_asset() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("github_release_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    let server = release_server()?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        config(
+            r#"{ github = "owner/repo", tag = "v1.0.0", asset = "\\.zip$", path = "my_plugin.aux2" }"#,
+        )
+        .as_bytes(),
+    )?;
+    let prepare = || {
+        Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+            .current_dir(&project_dir)
+            .env("AU2_GITHUB_API_BASE", server.url("/"))
+            .env("GITHUB_TOKEN", "secret-token")
+            .args(["prepare:artifacts", "--force"])
+            .assert()
+    };
+
+    prepare().success();
+    assert_eq!(
+        fs::read_to_string(
+            project_dir
+                .join("dev")
+                .join("data")
+                .join("Plugin")
+                .join("my_plugin.aux2")
+        )?,
+        "from github"
+    );
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/repos/owner/repo/releases/tags/v1.0.0");
+    assert_eq!(
+        requests[0].header("authorization"),
+        Some("Bearer secret-token")
+    );
+    // トークンがある場合は API 経由でダウンロードする
+    assert_eq!(requests[1].path, "/repos/owner/repo/releases/assets/1");
+    assert_eq!(
+        requests[1].header("authorization"),
+        Some("Bearer secret-token")
+    );
+
+    // タグを固定している場合は API を呼ばない
+    prepare().success();
+    assert_eq!(server.requests().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn github_source_rejects_ambiguous_asset() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("github_ambiguous_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    let server = release_server()?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        config(r#"{ github = "owner/repo", asset = "^my_plugin-" }"#).as_bytes(),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_GITHUB_API_BASE", server.url("/"))
+        .env_remove("GITHUB_TOKEN")
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("my_plugin-v2.0.0.zip.sha256"));
+    assert_eq!(
+        server.requests()[0].path,
+        "/repos/owner/repo/releases/latest"
+    );
+    assert!(server.requests()[0].header("authorization").is_none());
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
+mod common;
+
 use assert_cmd::Command;
 use fs_err as fs;
 use std::net::{TcpListener, TcpStream};
@@ -246,3 +248,456 @@ fn prepare_artifacts_downloads_http_source() -> Result<(), Box<dyn std::error::E
 
     Ok(())
 }
+
+#[test]
+fn prepare_artifacts_downloads_http_source_from_stub_server()
+-> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_stub_http_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    let server = common::StubServer::start(|request| match request.path.as_str() {
+        "/my_plugin.aux2" => common::StubResponse::ok(b"downloaded".to_vec()),
+        _ => common::StubResponse::status(404),
+    })?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"prepare\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = \"{}\"\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+            server.url("/my_plugin.aux2")
+        )
+        .as_bytes(),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .success();
+
+    let copied = project_dir
+        .join("dev")
+        .join("data")
+        .join("Plugin")
+        .join("my_plugin.aux2");
+    assert_eq!(fs::read(&copied)?, b"downloaded");
+    assert_eq!(server.requests().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn prepare_aviutl2_falls_back_to_next_api_base() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_api_base_project");
+    fs::create_dir_all(&project_dir)?;
+
+    let archive = common::zip_bytes(&[("aviutl2.exe", b"exe"), ("data/readme.txt", b"readme")]);
+    let server = common::StubServer::start(move |request| {
+        if request.path == "/mirror/download"
+            && request.query.get("version").map(String::as_str) == Some("2.0beta1")
+        {
+            common::StubResponse::ok(archive.clone())
+        } else {
+            common::StubResponse::status(404)
+        }
+    })?;
+
+    let config_path = project_dir.join("aviutl2.toml");
+    write_file(
+        &config_path,
+        br#"[project]
+name = "prepare"
+version = "0.1.0"
+
+[artifacts]
+
+[development]
+aviutl2_version = "2.0beta1"
+install_dir = "dev"
+api_base = "https://example.invalid"
+"#,
+    )?;
+
+    let dead_base = format!("http://127.0.0.1:{}", common::unused_port());
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env(
+            "AU2_API_BASE",
+            format!("{},{}", dead_base, server.url("/mirror/")),
+        )
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+
+    let install_dir = project_dir.join("dev");
+    assert_eq!(fs::read(install_dir.join("aviutl2.exe"))?, b"exe");
+    assert_eq!(
+        fs::read_to_string(install_dir.join(".aviutl2-version"))?,
+        "2.0beta1"
+    );
+    assert_eq!(server.requests().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn prepare_aviutl2_extracts_local_archive_and_detects_changes()
+-> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_local_archive_project");
+    fs::create_dir_all(&project_dir)?;
+
+    let archive_path = project_dir.join("vendor").join("aviutl2.zip");
+    write_file(
+        &archive_path,
+        &common::zip_bytes(&[("aviutl2.exe", b"first")]),
+    )?;
+
+    let config_path = project_dir.join("aviutl2.toml");
+    write_file(
+        &config_path,
+        br#"[project]
+name = "prepare"
+version = "0.1.0"
+
+[artifacts]
+
+[development]
+aviutl2_version = { path = "vendor/aviutl2.zip" }
+install_dir = "dev"
+"#,
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+    let aviutl_exe = project_dir.join("dev").join("aviutl2.exe");
+    assert_eq!(fs::read(&aviutl_exe)?, b"first");
+    let recorded = fs::read_to_string(project_dir.join("dev").join(".aviutl2-version"))?;
+    assert!(recorded.starts_with("path:vendor/aviutl2.zip:"));
+
+    write_file(
+        &archive_path,
+        &common::zip_bytes(&[("aviutl2.exe", b"second")]),
+    )?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+    assert_eq!(fs::read(&aviutl_exe)?, b"second");
+
+    Ok(())
+}
+
+#[test]
+fn prepare_aviutl2_copies_local_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_local_dir_project");
+    fs::create_dir_all(&project_dir)?;
+
+    let source_dir = temp.path().join("AviUtl2");
+    write_file(&source_dir.join("aviutl2.exe"), b"exe")?;
+    write_file(&source_dir.join("data").join("Plugin").join(".keep"), b"")?;
+
+    let config_path = project_dir.join("aviutl2.toml");
+    write_file(
+        &config_path,
+        format!(
+            "[project]\nname = \"prepare\"\nversion = \"0.1.0\"\n\n[artifacts]\n\n[development]\naviutl2_version = {{ dir = '{}' }}\ninstall_dir = \"dev\"\n",
+            source_dir.display()
+        )
+        .as_bytes(),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+    let install_dir = project_dir.join("dev");
+    assert_eq!(fs::read(install_dir.join("aviutl2.exe"))?, b"exe");
+    assert!(
+        install_dir
+            .join("data")
+            .join("Plugin")
+            .join(".keep")
+            .exists()
+    );
+    let recorded = fs::read_to_string(install_dir.join(".aviutl2-version"))?;
+    assert!(recorded.starts_with("dir:"));
+
+    Ok(())
+}
+
+#[test]
+fn prepare_aviutl2_upgrade_replaces_install_and_keeps_user_data()
+-> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_upgrade_project");
+    fs::create_dir_all(&project_dir)?;
+
+    write_file(
+        &project_dir.join("vendor").join("v1.zip"),
+        &common::zip_bytes(&[
+            ("aviutl2.exe", b"v1"),
+            ("data/aviutl2.conf", b"default"),
+            ("data/removed_upstream.txt", b"old"),
+        ]),
+    )?;
+    write_file(
+        &project_dir.join("vendor").join("v2.zip"),
+        &common::zip_bytes(&[("aviutl2.exe", b"v2"), ("data/aviutl2.conf", b"default")]),
+    )?;
+    write_file(
+        &project_dir.join("artifacts").join("my_plugin.aux2"),
+        b"dummy",
+    )?;
+    write_file(
+        &project_dir.join("artifacts").join("my_plugin_release.aux2"),
+        b"release",
+    )?;
+
+    let config = |version: &str| {
+        format!(
+            "[project]\nname = \"prepare\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = \"artifacts/my_plugin.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[artifacts.my_plugin.profiles.release]\nsource = \"artifacts/my_plugin_release.aux2\"\n\n[development]\naviutl2_version = {{ path = \"vendor/{version}.zip\" }}\ninstall_dir = \"dev\"\n"
+        )
+    };
+    let config_path = project_dir.join("aviutl2.toml");
+    write_file(&config_path, config("v1").as_bytes())?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare", "--profile", "release"])
+        .assert()
+        .success();
+
+    let data_dir = project_dir.join("dev").join("data");
+    write_file(&data_dir.join("aviutl2.conf"), b"user settings")?;
+    assert_eq!(
+        fs::read(data_dir.join("Plugin").join("my_plugin.aux2"))?,
+        b"release"
+    );
+
+    write_file(&config_path, config("v2").as_bytes())?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read(project_dir.join("dev").join("aviutl2.exe"))?,
+        b"v2"
+    );
+    assert_eq!(fs::read(data_dir.join("aviutl2.conf"))?, b"user settings");
+    assert!(!data_dir.join("removed_upstream.txt").exists());
+    // 前回と同じプロファイルで再配置する
+    assert_eq!(
+        fs::read(data_dir.join("Plugin").join("my_plugin.aux2"))?,
+        b"release"
+    );
+    assert!(!project_dir.join("dev.aviutl2-new").exists());
+    assert!(!project_dir.join("dev.aviutl2-old").exists());
+
+    Ok(())
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    use sha2::Digest;
+    sha2::Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[test]
+fn prepare_artifacts_verifies_http_source_checksum() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_checksum_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    let server = common::StubServer::start(|request| match request.path.as_str() {
+        "/my_plugin.aux2" => common::StubResponse::ok(b"downloaded".to_vec()),
+        _ => common::StubResponse::status(404),
+    })?;
+
+    let config = |sha256: &str| {
+        format!(
+            "[project]\nname = \"prepare\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = {{ url = \"{}\", sha256 = \"{}\" }}\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n",
+            server.url("/my_plugin.aux2"),
+            sha256
+        )
+    };
+    let config_path = project_dir.join("aviutl2.toml");
+
+    write_file(&config_path, config(&"0".repeat(64)).as_bytes())?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("prepare:artifacts")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("sha256 が一致しません"));
+    let copied = project_dir
+        .join("dev")
+        .join("data")
+        .join("Plugin")
+        .join("my_plugin.aux2");
+    assert!(!copied.exists());
+
+    write_file(&config_path, config(&sha256_hex(b"downloaded")).as_bytes())?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("prepare:artifacts")
+        .assert()
+        .success();
+    assert_eq!(fs::read(&copied)?, b"downloaded");
+
+    Ok(())
+}
+
+#[test]
+fn prepare_aviutl2_rejects_checksum_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_aviutl2_checksum_project");
+    fs::create_dir_all(&project_dir)?;
+
+    let archive = common::zip_bytes(&[("aviutl2.exe", b"exe")]);
+    let expected = sha256_hex(&archive);
+    let server = common::StubServer::start(move |_| common::StubResponse::ok(archive.clone()))?;
+
+    let config = |sha256: &str| {
+        format!(
+            "[project]\nname = \"prepare\"\nversion = \"0.1.0\"\n\n[artifacts]\n\n[development]\naviutl2_version = {{ version = \"2.0beta2\", sha256 = \"{sha256}\" }}\ninstall_dir = \"dev\"\n"
+        )
+    };
+    let config_path = project_dir.join("aviutl2.toml");
+
+    write_file(&config_path, config(&"f".repeat(64)).as_bytes())?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_API_BASE", server.url(""))
+        .arg("prepare:aviutl2")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("sha256 が一致しません"));
+    assert!(!project_dir.join("dev").join("aviutl2.exe").exists());
+
+    write_file(&config_path, config(&expected).as_bytes())?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .env("AU2_API_BASE", server.url(""))
+        .arg("prepare:aviutl2")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read(project_dir.join("dev").join("aviutl2.exe"))?,
+        b"exe"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn prepare_artifacts_extracts_files_from_zip_source() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_zip_source_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    let archive = common::zip_bytes(&[
+        ("x86/my_plugin.aux2", b"x86"),
+        ("x64/my_plugin.aux2", b"x64"),
+        ("x64/scripts/a.anm2", b"a"),
+        ("x64/scripts/b.anm2", b"b"),
+        ("readme.txt", b"readme"),
+    ]);
+    let server = common::StubServer::start(move |_| common::StubResponse::ok(archive.clone()))?;
+    let url = server.url("/my_plugin.zip");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"prepare\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = {{ url = \"{url}\", path = \"x64/my_plugin.aux2\" }}\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[artifacts.scripts]\nsource = {{ url = \"{url}\", path = \"x64/scripts/*.anm2\" }}\ndestination = \"Script/my_plugin\"\nplacement_method = \"copy\"\n\n[artifacts.missing]\nsource = {{ url = \"{url}\", path = \"x64/missing.aux2\" }}\ndestination = \"Plugin/missing.aux2\"\nplacement_method = \"copy\"\nenabled = false\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n"
+        )
+        .as_bytes(),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .success();
+
+    let data_dir = project_dir.join("dev").join("data");
+    assert_eq!(
+        fs::read_to_string(data_dir.join("Plugin").join("my_plugin.aux2"))?,
+        "x64"
+    );
+    let script_dir = data_dir.join("Script").join("my_plugin");
+    assert_eq!(fs::read_to_string(script_dir.join("a.anm2"))?, "a");
+    assert_eq!(fs::read_to_string(script_dir.join("b.anm2"))?, "b");
+    assert!(!data_dir.join("readme.txt").exists());
+    assert_eq!(server.requests().len(), 1);
+
+    let config = fs::read_to_string(project_dir.join("aviutl2.toml"))?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        config.replace("enabled = false\n", "").as_bytes(),
+    )?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("x64/missing.aux2"));
+
+    Ok(())
+}
+
+#[test]
+fn prepare_artifacts_rejects_corrupted_zip_source() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("prepare_corrupted_zip_project");
+    fs::create_dir_all(&project_dir)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+
+    // ローカルヘッダーとセントラルディレクトリの CRC を書き換える
+    let mut archive = common::zip_bytes(&[("x64/my_plugin.aux2", b"x64")]);
+    for (signature, offset) in [(b"PK\x03\x04", 14), (b"PK\x01\x02", 16)] {
+        let position = archive
+            .windows(4)
+            .position(|window| window == signature)
+            .unwrap();
+        archive[position + offset] ^= 0xff;
+    }
+    let server = common::StubServer::start(move |_| common::StubResponse::ok(archive.clone()))?;
+    let url = server.url("/my_plugin.zip");
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"prepare\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = {{ url = \"{url}\", path = \"x64/my_plugin.aux2\" }}\ndestination = \"Plugin/my_plugin.aux2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n"
+        )
+        .as_bytes(),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["prepare:artifacts", "--force"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("zip が破損しています"));
+    assert!(
+        !project_dir
+            .join("dev")
+            .join("data")
+            .join("Plugin")
+            .join("my_plugin.aux2")
+            .exists()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,321 @@
+mod common;
+
+use assert_cmd::Command;
+use fs_err as fs;
+use predicates::prelude::PredicateBooleanExt;
+use std::path::Path;
+use tempfile::tempdir;
+
+const MACHINE_AMD64: u16 = 0x8664;
+const MACHINE_I386: u16 = 0x014c;
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+fn write_project(project_dir: &Path, plugin: &[u8]) -> Result<(), std::io::Error> {
+    write_file(&project_dir.join("build").join("my_input.aui2"), plugin)?;
+    write_file(&project_dir.join("dev").join("aviutl2.exe"), b"")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"release\"\nversion = \"0.1.0\"\n\n[artifacts.my_input]\nsource = \"build/my_input.aui2\"\ndestination = \"Plugin/my_input.aui2\"\nplacement_method = \"copy\"\n\n[development]\naviutl2_version = \"latest\"\ninstall_dir = \"dev\"\n\n[release]\noutput_dir = \"out\"\n",
+    )
+}
+
+#[test]
+fn release_packages_valid_plugin() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("release_valid_project");
+    write_project(
+        &project_dir,
+        &common::pe_dll(MACHINE_AMD64, &["GetInputPluginTable"]),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("release")
+        .assert()
+        .success();
+    assert!(
+        project_dir
+            .join("out")
+            .join("release-v0.1.0.au2pkg.zip")
+            .exists()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn release_rejects_plugin_for_wrong_architecture() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("release_x86_project");
+    write_project(
+        &project_dir,
+        &common::pe_dll(MACHINE_I386, &["GetInputPluginTable"]),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("release")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("x64 向けにビルドされていません"));
+    assert!(
+        !project_dir
+            .join("out")
+            .join("release-v0.1.0.au2pkg.zip")
+            .exists()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn release_rejects_plugin_without_entry_point() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("release_no_entry_project");
+    write_project(
+        &project_dir,
+        &common::pe_dll(MACHINE_AMD64, &["GetFilterPluginTable"]),
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("release")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("GetInputPluginTable"));
+
+    // develop では警告のみで配置は行う
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .args(["develop", "--skip-start"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("GetInputPluginTable"));
+    assert!(
+        project_dir
+            .join("dev")
+            .join("data")
+            .join("Plugin")
+            .join("my_input.aui2")
+            .exists()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn release_rejects_malformed_plugin_without_panicking() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("release_malformed_project");
+    let mut plugin = common::pe_dll(MACHINE_AMD64, &["GetInputPluginTable"]);
+    // セクションのファイル上の位置を u32 の上限近くにして、RVA の変換があふれるようにする
+    let raw_offset = 0x44 + 20 + 240 + 20;
+    plugin[raw_offset..raw_offset + 4].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+    write_project(&project_dir, &plugin)?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("release")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "有効な PE ファイルではありません",
+        ))
+        .stderr(predicates::str::contains("panicked").not());
+
+    Ok(())
+}
+
+#[test]
+fn release_accepts_general_plugin_as_x64_dll() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("release_aux2_project");
+    write_file(
+        &project_dir.join("build").join("my_plugin.aux2"),
+        &common::pe_dll(MACHINE_AMD64, &[]),
+    )?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        b"[project]\nname = \"release\"\nversion = \"0.1.0\"\n\n[artifacts.my_plugin]\nsource = \"build/my_plugin.aux2\"\ndestination = \"Plugin/my_plugin.aux2\"\n\n[release]\noutput_dir = \"out\"\n",
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("release")
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+fn release_zip_with_version(
+    project_dir: &Path,
+    version: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    write_file(&project_dir.join("build").join("readme.txt"), b"readme")?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        format!(
+            "[project]\nname = \"versioned\"\nversion = {version}\n\n[artifacts.readme]\nsource = \"build/readme.txt\"\ndestination = \"Script/readme.txt\"\n\n[release]\noutput_dir = \"out\"\n"
+        )
+        .as_bytes(),
+    )?;
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(project_dir)
+        .arg("release")
+        .assert()
+        .success();
+    let mut names = fs::read_dir(project_dir.join("out"))?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, std::io::Error>>()?;
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn release_reads_version_from_other_files() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+
+    // version.workspace = true はワークスペースの Cargo.toml から読む
+    let workspace_dir = temp.path().join("workspace");
+    write_file(
+        &workspace_dir.join("Cargo.toml"),
+        b"[workspace]\nmembers = [\"plugin\"]\n\n[workspace.package]\nversion = \"1.2.3\"\n",
+    )?;
+    let cargo_dir = workspace_dir.join("plugin");
+    write_file(
+        &cargo_dir.join("Cargo.toml"),
+        b"[package]\nname = \"plugin\"\nversion.workspace = true\n",
+    )?;
+    assert_eq!(
+        release_zip_with_version(&cargo_dir, "{ from = \"Cargo.toml\" }")?,
+        ["versioned-v1.2.3.au2pkg.zip"]
+    );
+
+    let node_dir = temp.path().join("node");
+    write_file(
+        &node_dir.join("package.json"),
+        br#"{ "name": "plugin", "version": "2.0.0-beta.1" }"#,
+    )?;
+    assert_eq!(
+        release_zip_with_version(&node_dir, "{ from = \"package.json\" }")?,
+        ["versioned-v2.0.0-beta.1.au2pkg.zip"]
+    );
+
+    let file_dir = temp.path().join("file");
+    write_file(&file_dir.join("VERSION"), b"3.1.4\n")?;
+    assert_eq!(
+        release_zip_with_version(&file_dir, "{ file = \"VERSION\" }")?,
+        ["versioned-v3.1.4.au2pkg.zip"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn release_reads_version_from_git_tag() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("tagged");
+    write_file(&project_dir.join("README.md"), b"tagged")?;
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .current_dir(&project_dir)
+            .args(["-c", "user.name=au2", "-c", "user.email=au2@example.com"])
+            .args(args)
+            .assert()
+            .success();
+    };
+    git(&["init", "--quiet"]);
+    git(&["add", "README.md"]);
+    git(&["commit", "--quiet", "-m", "initial"]);
+    git(&["tag", "v0.4.0"]);
+    git(&["tag", "nightly"]);
+
+    assert_eq!(
+        release_zip_with_version(&project_dir, "{ from = \"git-tag\", prefix = \"v\" }")?,
+        ["versioned-v0.4.0.au2pkg.zip"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn release_expands_environment_in_zip_name_and_catalog_pattern()
+-> Result<(), Box<dyn std::error::Error>> {
+    let temp = tempdir()?;
+    let project_dir = temp.path().join("release_env_project");
+    write_file(&project_dir.join("build").join("readme.txt"), b"readme")?;
+    write_file(
+        &project_dir.join("package_template.txt"),
+        "{name} v{version}\n${env:AU2_TEST_NOT_EXPANDED}\n".as_bytes(),
+    )?;
+    write_file(
+        &project_dir.join("aviutl2.toml"),
+        br#"[project]
+name = "release"
+version = "0.1.0"
+
+[artifacts.readme]
+source = "build/readme.txt"
+destination = "Script/readme.txt"
+
+[release]
+output_dir = "out"
+zip_name = "{name}-${env:AU2_TEST_CHANNEL}-v{version}"
+package_template = "package_template.txt"
+
+[catalog]
+id = "release"
+name = "Release"
+type = "script"
+author = "au2"
+summary = "summary"
+homepage = "https://example.com"
+description = "desc"
+
+[catalog.license]
+type = "CC0-1.0"
+
+[catalog.download_source]
+type = "github"
+owner = "owner"
+repo = "release"
+"#,
+    )?;
+
+    Command::new(assert_cmd::cargo::cargo_bin!("au2"))
+        .current_dir(&project_dir)
+        .arg("release")
+        .env("AU2_TEST_CHANNEL", "beta")
+        .env_remove("AU2_TEST_NOT_EXPANDED")
+        .assert()
+        .success();
+
+    let zip_path = project_dir
+        .join("out")
+        .join("release-beta-v0.1.0.au2pkg.zip");
+    assert!(zip_path.exists());
+    let catalog: serde_json::Value = serde_json::from_str(&fs::read_to_string(
+        project_dir.join("out").join("catalog.json"),
+    )?)?;
+    assert_eq!(
+        catalog[0]["installer"]["source"]["github"]["pattern"],
+        "^release-beta-v[^/]+\\.au2pkg\\.zip$"
+    );
+    // package.txt の `${env:...}` は展開しない
+    let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path)?)?;
+    let mut package = String::new();
+    std::io::Read::read_to_string(&mut archive.by_name("package.txt")?, &mut package)?;
+    assert_eq!(
+        package,
+        "release v0.1.0\r\n${env:AU2_TEST_NOT_EXPANDED}\r\n"
+    );
+
+    Ok(())
+}